@@ -1,4 +1,8 @@
+use micromath::F32Ext;
+
 use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::sequencer::euclid::Euclid;
 use crate::sequencer::sequencer::GateMode;
 
 impl Note {
@@ -7,6 +11,18 @@ impl Note {
     }
 }
 
+impl Pitch {
+    /// 1V/oct in 12-TET, reading the pitch as a MIDI note number, with octave 0 lining up with
+    /// `Note::voltage`. `Tuning::voltage` gives the voltage under other tunings.
+    pub fn voltage(self) -> f32 {
+        semitone_voltage(self.0 as f32)
+    }
+}
+
+pub(crate) fn semitone_voltage(pitch: f32) -> f32 {
+    (pitch - Pitch::from(Note::C).0 as f32 + 1.0) / Note::COUNT as f32
+}
+
 impl GateMode {
     pub fn from_float(f: f32) -> GateMode {
         if f < 0.25 {
@@ -20,3 +36,11 @@ impl GateMode {
         }
     }
 }
+
+impl Euclid {
+    /// Maps two pots onto the number of hits and the rotation over `steps` steps.
+    pub fn from_float(hits: f32, rotation: f32, steps: u8) -> Euclid {
+        let scale = |f: f32| F32Ext::round(f.clamp(0.0, 1.0) * steps as f32) as u8;
+        Euclid::new(scale(hits), steps, scale(rotation))
+    }
+}
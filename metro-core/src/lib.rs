@@ -1,8 +1,10 @@
-#![no_std]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 pub mod musical;
 pub mod sequencer;
 pub mod analog;
+pub mod modulation;
+pub mod tuning;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,88 @@
+use crate::musical::gate::Gate;
+
+/// Attack and decay times of a stage's envelope.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Envelope {
+    pub attack_us: u32,
+    pub decay_us: u32,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope { attack_us: 0, decay_us: 100_000 }
+    }
+}
+
+/// Runs an attack-decay envelope, triggered whenever the gate opens. The gate only starts it,
+/// so the envelope runs its course however short the gate is. Values lie in 0..1.
+#[derive(Debug, Clone, Copy)]
+pub struct EnvelopeGenerator {
+    gate: Gate,
+    triggered_us: Option<u32>,
+    /// Level when last triggered, which the attack rises from so retriggers don't click.
+    start: f32,
+    level: f32,
+}
+
+impl EnvelopeGenerator {
+    pub fn new() -> EnvelopeGenerator {
+        EnvelopeGenerator { gate: Gate::Closed, triggered_us: None, start: 0.0, level: 0.0 }
+    }
+
+    pub fn level(&self) -> f32 { self.level }
+
+    /// Advances the envelope to `now_us`, using the times of the stage whose gate is given.
+    pub fn update(&mut self, gate: Gate, envelope: Envelope, now_us: u32) -> f32 {
+        if gate == Gate::Open && self.gate == Gate::Closed {
+            self.triggered_us = Some(now_us);
+            self.start = self.level;
+        }
+        self.gate = gate;
+        self.level = match self.triggered_us {
+            None => 0.0,
+            Some(triggered_us) => {
+                let elapsed = now_us.wrapping_sub(triggered_us);
+                if elapsed < envelope.attack_us {
+                    self.start + (1.0 - self.start) * elapsed as f32 / envelope.attack_us as f32
+                } else if elapsed - envelope.attack_us < envelope.decay_us {
+                    1.0 - (elapsed - envelope.attack_us) as f32 / envelope.decay_us as f32
+                } else {
+                    self.triggered_us = None;
+                    0.0
+                }
+            }
+        };
+        self.level
+    }
+}
+
+impl Default for EnvelopeGenerator {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modulation::envelope::{Envelope, EnvelopeGenerator};
+    use crate::musical::gate::Gate;
+
+    #[test]
+    fn test_envelope() {
+        let envelope = Envelope { attack_us: 100, decay_us: 200 };
+        let mut generator = EnvelopeGenerator::new();
+        assert_eq!(0.0, generator.update(Gate::Closed, envelope, 0));
+        assert_eq!(0.0, generator.update(Gate::Open, envelope, 1000));
+        assert_eq!(0.5, generator.update(Gate::Closed, envelope, 1050));
+        assert_eq!(1.0, generator.update(Gate::Closed, envelope, 1100));
+        assert_eq!(0.5, generator.update(Gate::Closed, envelope, 1200));
+
+        assert_eq!(0.5, generator.update(Gate::Open, envelope, 1200));
+        assert_eq!(0.75, generator.update(Gate::Open, envelope, 1250));
+        assert_eq!(0.0, generator.update(Gate::Open, envelope, 1500));
+        assert_eq!(0.0, generator.update(Gate::Open, envelope, 1600));
+
+        let pluck = Envelope { attack_us: 0, decay_us: 100 };
+        assert_eq!(0.0, generator.update(Gate::Closed, pluck, u32::MAX - 10));
+        assert_eq!(1.0, generator.update(Gate::Open, pluck, u32::MAX));
+        assert_eq!(0.5, generator.update(Gate::Open, pluck, 49));
+    }
+}
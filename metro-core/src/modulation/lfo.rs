@@ -0,0 +1,125 @@
+use micromath::F32Ext;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    /// Holds a new random value for each cycle.
+    SampleAndHold,
+}
+
+/// A low frequency oscillator synced to the clock, with one cycle lasting a whole number of
+/// pulses. It is stepped alongside the sequencer, and its value lies in 0..1.
+#[derive(Debug, Clone)]
+pub struct Lfo {
+    shape: LfoShape,
+    pulses: u8,
+    pulse: u8,
+    pulse_time_us: u32,
+    held: f32,
+    rng: oorandom::Rand32,
+}
+
+impl Lfo {
+    pub fn new(shape: LfoShape, pulses: u8) -> Lfo {
+        Lfo { shape, pulses: pulses.max(1), pulse: 0, pulse_time_us: 0, held: 0.5, rng: oorandom::Rand32::new(0) }
+    }
+
+    pub fn shape(&self) -> LfoShape { self.shape }
+
+    pub fn pulses(&self) -> u8 { self.pulses }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape
+    }
+
+    /// Sets the length of one cycle in pulses.
+    pub fn set_pulses(&mut self, pulses: u8) {
+        self.pulses = pulses.max(1);
+        self.pulse %= self.pulses
+    }
+
+    /// Sets the expected time between pulses, used to move smoothly from one pulse to the next.
+    pub fn set_pulse_time_us(&mut self, pulse_time_us: u32) {
+        self.pulse_time_us = pulse_time_us
+    }
+
+    pub fn set_rnd_seed(&mut self, seed: u64) {
+        self.rng = oorandom::Rand32::new(seed)
+    }
+
+    pub fn step(&mut self) {
+        self.pulse = (self.pulse + 1) % self.pulses;
+        if self.pulse == 0 {
+            self.held = self.rng.rand_float()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pulse = 0;
+        self.held = self.rng.rand_float()
+    }
+
+    /// Position in the cycle in 0..1. Within a pulse it stops short of the next pulse's
+    /// position, so a late clock never makes it jump backwards.
+    pub fn phase(&self, last_beat_us: u32) -> f32 {
+        let within = match self.pulse_time_us {
+            0 => 0.0,
+            pulse_time_us => (last_beat_us as f32 / pulse_time_us as f32).min(1.0),
+        };
+        (self.pulse as f32 + within) / self.pulses as f32
+    }
+
+    pub fn value(&self, last_beat_us: u32) -> f32 {
+        let phase = self.phase(last_beat_us);
+        match self.shape {
+            LfoShape::Sine => 0.5 - 0.5 * F32Ext::cos(phase * 2.0 * core::f32::consts::PI),
+            LfoShape::Triangle if phase < 0.5 => phase * 2.0,
+            LfoShape::Triangle => 2.0 - phase * 2.0,
+            LfoShape::Square if phase < 0.5 => 1.0,
+            LfoShape::Square => 0.0,
+            LfoShape::SampleAndHold => self.held,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modulation::lfo::{Lfo, LfoShape};
+
+    #[test]
+    fn test_lfo() {
+        let mut lfo = Lfo::new(LfoShape::Triangle, 4);
+        lfo.set_pulse_time_us(1000);
+        assert_eq!(0.0, lfo.value(0));
+        assert_eq!(0.25, lfo.value(500));
+        lfo.step();
+        assert_eq!(0.5, lfo.value(0));
+        assert_eq!(1.0, lfo.value(2000));
+        lfo.step();
+        assert_eq!(1.0, lfo.value(0));
+        lfo.set_shape(LfoShape::Square);
+        assert_eq!(0.0, lfo.value(0));
+
+        lfo.set_shape(LfoShape::Sine);
+        assert!(lfo.value(0) > 0.99);
+        lfo.step();
+        assert!((lfo.value(0) - 0.5).abs() < 0.01);
+        lfo.step();
+        assert!(lfo.value(0) < 0.01);
+
+        lfo.set_shape(LfoShape::SampleAndHold);
+        let held = lfo.value(0);
+        lfo.step();
+        assert_eq!(held, lfo.value(500));
+        lfo.step();
+        lfo.step();
+        lfo.step();
+        assert_ne!(held, lfo.value(0));
+
+        lfo.set_pulses(3);
+        lfo.reset();
+        assert_eq!(0.0, lfo.phase(0));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod envelope;
+pub mod lfo;
+pub mod slew;
+
+/// Scales a modulation value in 0..1 to a MIDI CC value.
+pub fn cc(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 127.0 + 0.5) as u8
+}
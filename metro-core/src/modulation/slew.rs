@@ -0,0 +1,111 @@
+/// How fast a `SlewLimiter` may follow its target, separately for rising and falling values.
+/// A rate of 0 follows the target straight away.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Slew {
+    /// Moves at most this many units per millisecond.
+    Linear { rise_per_ms: u32, fall_per_ms: u32 },
+    /// Closes the gap like an RC filter with the given time constant.
+    Exponential { rise_us: u32, fall_us: u32 },
+}
+
+/// Limits how fast a value changes, for glide, smoothing CV lanes or de-clicking DAC jumps.
+/// Values are plain integers such as DAC codes or millivolts, and only integer arithmetic is
+/// used, so it runs cheaply on cores without an FPU like the Cortex-M0+.
+#[derive(Debug, Clone, Copy)]
+pub struct SlewLimiter {
+    slew: Slew,
+    value: i32,
+    /// Progress towards the next whole unit of a linear slew, in thousandths.
+    carry: u32,
+}
+
+impl SlewLimiter {
+    pub fn new(slew: Slew, value: i32) -> SlewLimiter {
+        SlewLimiter { slew, value, carry: 0 }
+    }
+
+    pub fn slew(&self) -> Slew { self.slew }
+
+    pub fn value(&self) -> i32 { self.value }
+
+    pub fn set_slew(&mut self, slew: Slew) {
+        self.slew = slew
+    }
+
+    /// Jumps straight to `value`.
+    pub fn set_value(&mut self, value: i32) {
+        self.value = value;
+        self.carry = 0
+    }
+
+    /// Moves towards `target` by as much as `elapsed_us` allows and returns the new value.
+    pub fn update(&mut self, target: i32, elapsed_us: u32) -> i32 {
+        let diff = target as i64 - self.value as i64;
+        let rising = diff > 0;
+        let step = match self.slew {
+            Slew::Linear { rise_per_ms, fall_per_ms } => {
+                let rate = if rising { rise_per_ms } else { fall_per_ms };
+                match rate {
+                    0 => diff.unsigned_abs(),
+                    rate => {
+                        let progress = rate as u64 * elapsed_us as u64 + self.carry as u64;
+                        self.carry = (progress % 1000) as u32;
+                        progress / 1000
+                    }
+                }
+            }
+            Slew::Exponential { rise_us, fall_us } => {
+                let time_constant_us = if rising { rise_us } else { fall_us };
+                let step = diff.unsigned_abs() * elapsed_us as u64 / (time_constant_us as u64 + elapsed_us as u64).max(1);
+                // Always make some progress, or small gaps would never close.
+                step.max((elapsed_us > 0) as u64)
+            }
+        };
+        // Stepping no further than the target keeps the value within i32.
+        let step = step.min(diff.unsigned_abs()) as i64;
+        let value = self.value as i64 + if rising { step } else { -step };
+        if value == target as i64 {
+            self.set_value(target);
+        } else {
+            self.value = value as i32;
+        }
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modulation::slew::{Slew, SlewLimiter};
+
+    #[test]
+    fn test_linear() {
+        let mut slew = SlewLimiter::new(Slew::Linear { rise_per_ms: 2, fall_per_ms: 0 }, 0);
+        assert_eq!(2, slew.update(10, 1000));
+        assert_eq!(2, slew.update(10, 400));
+        assert_eq!(3, slew.update(10, 400));
+        assert_eq!(10, slew.update(10, 5000));
+        assert_eq!(-5, slew.update(-5, 1));
+
+        slew.set_slew(Slew::Linear { rise_per_ms: 4095, fall_per_ms: 1 });
+        assert_eq!(4090, slew.update(4095, 1000));
+        assert_eq!(4089, slew.update(0, 1500));
+    }
+
+    #[test]
+    fn test_exponential() {
+        let mut slew = SlewLimiter::new(Slew::Exponential { rise_us: 1000, fall_us: 0 }, 0);
+        assert_eq!(500, slew.update(1000, 1000));
+        assert_eq!(750, slew.update(1000, 1000));
+        assert_eq!(750, slew.update(1000, 0));
+        let mut value = 0;
+        for _ in 0..20 {
+            value = slew.update(1000, 1000);
+        }
+        assert_eq!(1000, value);
+        assert_eq!(0, slew.update(0, 1));
+
+        let mut full_scale = SlewLimiter::new(Slew::Exponential { rise_us: 1, fall_us: 1 }, i32::MIN);
+        assert_eq!(i32::MAX - 4295, full_scale.update(i32::MAX, 1_000_000));
+        assert_eq!(i32::MIN + 4295, full_scale.update(i32::MIN, 1_000_000));
+    }
+}
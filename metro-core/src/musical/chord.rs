@@ -0,0 +1,81 @@
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChordType {
+    Major = 0,
+    Minor = 1,
+    Diminished = 2,
+    Augmented = 3,
+    Sus2 = 4,
+    Sus4 = 5,
+    Major7 = 6,
+    Minor7 = 7,
+    Dominant7 = 8,
+    Octaves = 9,
+}
+
+static MAJOR: [u8; 3] = [0, 4, 7];
+static MINOR: [u8; 3] = [0, 3, 7];
+static DIMINISHED: [u8; 3] = [0, 3, 6];
+static AUGMENTED: [u8; 3] = [0, 4, 8];
+static SUS2: [u8; 3] = [0, 2, 7];
+static SUS4: [u8; 3] = [0, 5, 7];
+static MAJOR7: [u8; 4] = [0, 4, 7, 11];
+static MINOR7: [u8; 4] = [0, 3, 7, 10];
+static DOMINANT7: [u8; 4] = [0, 4, 7, 10];
+static OCTAVES: [u8; 3] = [0, 12, 24];
+
+impl ChordType {
+    /// Semitones above the root.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            ChordType::Major => &MAJOR,
+            ChordType::Minor => &MINOR,
+            ChordType::Diminished => &DIMINISHED,
+            ChordType::Augmented => &AUGMENTED,
+            ChordType::Sus2 => &SUS2,
+            ChordType::Sus4 => &SUS4,
+            ChordType::Major7 => &MAJOR7,
+            ChordType::Minor7 => &MINOR7,
+            ChordType::Dominant7 => &DOMINANT7,
+            ChordType::Octaves => &OCTAVES,
+        }
+    }
+}
+
+/// Up to `CAPACITY` chord tones as semitones above a root, kept in the order they were given.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Chord {
+    intervals: [u8; Chord::CAPACITY],
+    len: u8,
+}
+
+impl Chord {
+    pub const CAPACITY: usize = 6;
+
+    /// Intervals beyond `CAPACITY` are dropped; an empty chord plays just the root.
+    pub fn new(intervals: &[u8]) -> Chord {
+        let mut chord = Chord { intervals: [0; Self::CAPACITY], len: 1 };
+        let len = intervals.len().min(Self::CAPACITY);
+        if len > 0 {
+            chord.intervals[..len].copy_from_slice(&intervals[..len]);
+            chord.len = len as u8;
+        }
+        chord
+    }
+
+    pub fn intervals(&self) -> &[u8] {
+        &self.intervals[..self.len as usize]
+    }
+
+    /// The same tones from lowest to highest.
+    pub fn sorted(&self) -> Chord {
+        let mut chord = *self;
+        chord.intervals[..self.len as usize].sort_unstable();
+        chord
+    }
+}
+
+impl From<ChordType> for Chord {
+    fn from(chord_type: ChordType) -> Chord {
+        Chord::new(chord_type.intervals())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::musical::scale::Scale;
+use crate::musical::scale_mask::ScaleMask;
+use crate::tuning::system::NoteSteps;
+
+/// A scale played from a root note, e.g. D Dorian.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Key {
+    pub scale: ScaleMask,
+    pub root: Note,
+}
+
+impl Key {
+    /// Takes a built-in `Scale` as well as a custom `ScaleMask`.
+    pub fn new(scale: impl Into<ScaleMask>, root: Note) -> Key {
+        Key { scale: scale.into(), root }
+    }
+
+    /// Like `ScaleMask::quantize_float`, with the root taking the place of the scale's C.
+    pub fn quantize_float(self, input: f32, base: Pitch, octaves: u8) -> Pitch {
+        self.quantize_float_in(input, base, octaves, NoteSteps::EDO_12)
+    }
+
+    /// Returns the nearest pitch in the key, which may lie in a neighbouring octave.
+    pub fn quantize(self, input: Pitch) -> Pitch {
+        self.quantize_in(input, NoteSteps::EDO_12)
+    }
+
+    /// Like `quantize_float`, counting in the steps of a tuning. A chromatic key spans every
+    /// step of the tuning.
+    pub fn quantize_float_in(self, input: f32, base: Pitch, octaves: u8, steps: NoteSteps) -> Pitch {
+        self.scale.quantize_float_in(input, base, self.root, octaves, steps)
+    }
+
+    /// Like `quantize`, counting in the steps of a tuning. A chromatic key passes every step
+    /// of the tuning through.
+    pub fn quantize_in(self, input: Pitch, steps: NoteSteps) -> Pitch {
+        self.scale.quantize_in(input, self.root, steps)
+    }
+}
+
+impl From<Scale> for Key {
+    fn from(scale: Scale) -> Key {
+        Key::new(scale, Note::C)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::key::Key;
+    use crate::musical::note::Note;
+    use crate::musical::pitch::Pitch;
+    use crate::musical::scale::Scale;
+    use crate::musical::scale_mask::ScaleMask;
+
+    #[test]
+    fn test_key() {
+        let d_dorian = Key::new(Scale::Dorian, Note::D);
+        assert_eq!(Pitch(62), d_dorian.quantize(Pitch(63)));
+        assert_eq!(Pitch(71), d_dorian.quantize(Pitch(71)));
+        assert_eq!(Pitch(62), d_dorian.quantize_float(0.0, Pitch(60), 1));
+        assert_eq!(Pitch(72), d_dorian.quantize_float(1.0, Pitch(60), 1));
+
+        let f_sharp_pentatonic = Key::new(Scale::MinorPentatonic, Note::FSharp);
+        assert_eq!(Pitch(66), f_sharp_pentatonic.quantize(Pitch(67)));
+        assert_eq!(Pitch(61), f_sharp_pentatonic.quantize(Pitch(62)));
+        assert_eq!(Pitch(1), f_sharp_pentatonic.quantize(Pitch(0)));
+
+        let d_triad = Key::new(ScaleMask::new(0b_0000_1001_0001), Note::D);
+        assert_eq!(Pitch(66), d_triad.quantize(Pitch(65)));
+        assert_eq!(Pitch(69), d_triad.quantize_float(1.0, Pitch(60), 1));
+    }
+}
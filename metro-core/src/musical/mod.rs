@@ -1,3 +1,7 @@
+pub mod chord;
 pub mod note;
+pub mod pitch;
 pub mod scale;
+pub mod scale_mask;
 pub mod gate;
+pub mod key;
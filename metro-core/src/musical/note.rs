@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Note {
     C = 0,
     CSharp = 1,
@@ -16,8 +16,13 @@ pub enum Note {
 
 impl Note {
     pub const COUNT: u8 = 12;
+    pub const ALL: [Note; 12] = [Note::C, Note::CSharp, Note::D, Note::DSharp, Note::E, Note::F, Note::FSharp, Note::G, Note::GSharp, Note::A, Note::ASharp, Note::B];
+
+    pub fn from_index(index: u8) -> Note {
+        Self::ALL[(index % Self::COUNT) as usize]
+    }
 
     pub fn distance(self, b: Note) -> u8 {
-        (self as i8 - b as i8).abs() as u8
+        (self as i8 - b as i8).unsigned_abs()
     }
 }
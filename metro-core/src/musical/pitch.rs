@@ -0,0 +1,68 @@
+use crate::musical::note::Note;
+
+/// A note with its octave, stored as a MIDI note number (C4 = 60).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Pitch(pub u8);
+
+impl Pitch {
+    pub const MIN: Pitch = Pitch(0);
+    pub const MAX: Pitch = Pitch(127);
+
+    /// Octaves follow MIDI numbering, so the lowest octave is -1.
+    pub fn new(note: Note, octave: i8) -> Pitch {
+        Self::from_i16((octave as i16 + 1) * Note::COUNT as i16 + note as i16)
+    }
+
+    pub fn note(self) -> Note {
+        Note::from_index(self.0 % Note::COUNT)
+    }
+
+    pub fn octave(self) -> i8 {
+        (self.0 / Note::COUNT) as i8 - 1
+    }
+
+    pub fn transpose(self, semitones: i16) -> Pitch {
+        Self::from_i16(self.0 as i16 + semitones)
+    }
+
+    pub fn distance(self, b: Pitch) -> u8 {
+        (self.0 as i16 - b.0 as i16).unsigned_abs() as u8
+    }
+
+    fn from_i16(value: i16) -> Pitch {
+        Pitch(value.clamp(Self::MIN.0 as i16, Self::MAX.0 as i16) as u8)
+    }
+}
+
+impl From<Note> for Pitch {
+    /// Places the note in octave 0.
+    fn from(note: Note) -> Pitch {
+        Pitch::new(note, 0)
+    }
+}
+
+impl From<Pitch> for Note {
+    fn from(pitch: Pitch) -> Note {
+        pitch.note()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::note::Note;
+    use crate::musical::pitch::Pitch;
+    use crate::musical::scale::Scale;
+
+    #[test]
+    fn test_pitch() {
+        assert_eq!(Pitch(60), Pitch::new(Note::C, 4));
+        assert_eq!(Note::FSharp, Pitch(66).note());
+        assert_eq!(-1, Pitch(11).octave());
+        assert_eq!(Pitch::MAX, Pitch::new(Note::B, 12));
+
+        assert_eq!(Pitch(60), Scale::Major.quantize(Pitch(60)));
+        assert_eq!(Pitch(72), Scale::MajorPentatonic.quantize(Pitch(71)));
+        assert_eq!(Pitch(24), Scale::Major.quantize_float(0.0, Pitch(24), 2));
+        assert_eq!(Pitch(47), Scale::Major.quantize_float(1.0, Pitch(24), 2));
+    }
+}
@@ -1,7 +1,7 @@
-use micromath::F32Ext;
-
 use crate::musical::note::Note;
 use crate::musical::note::Note::*;
+use crate::musical::pitch::Pitch;
+use crate::musical::scale_mask::ScaleMask;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Scale {
@@ -42,31 +42,31 @@ static MAJOR: [Note; 7] = [C, D, E, F, G, A, B];
 static MINOR: [Note; 7] = [C, D, DSharp, F, G, GSharp, ASharp];
 static DORIAN: [Note; 7] = [C, D, DSharp, F, G, A, ASharp];
 static MIXOLYDIAN: [Note; 7] = [C, D, E, F, G, A, ASharp];
-static LYDIA: [Note; 7] = [C, D, E, GSharp, G, A, B];
+static LYDIA: [Note; 7] = [C, D, E, FSharp, G, A, B];
 static PHRYGIAN: [Note; 7] = [C, CSharp, DSharp, F, G, GSharp, ASharp];
-static LOCRIAN: [Note; 7] = [C, CSharp, DSharp, E, G, GSharp, ASharp];
-static DIMINISHED: [Note; 8] = [C, CSharp, DSharp, E, GSharp, G, A, ASharp];
-static WHOLE_HALF: [Note; 8] = [C, D, DSharp, F, GSharp, GSharp, A, B];
-static WHOLE_TONE: [Note; 6] = [C, D, E, GSharp, GSharp, ASharp];
-static MINOR_BLUES: [Note; 6] = [C, DSharp, F, GSharp, G, ASharp];
+static LOCRIAN: [Note; 7] = [C, CSharp, DSharp, F, FSharp, GSharp, ASharp];
+static DIMINISHED: [Note; 8] = [C, CSharp, DSharp, E, FSharp, G, A, ASharp];
+static WHOLE_HALF: [Note; 8] = [C, D, DSharp, F, FSharp, GSharp, A, B];
+static WHOLE_TONE: [Note; 6] = [C, D, E, FSharp, GSharp, ASharp];
+static MINOR_BLUES: [Note; 6] = [C, DSharp, F, FSharp, G, ASharp];
 static MINOR_PENTATONIC: [Note; 5] = [C, DSharp, F, G, ASharp];
 static MAJOR_PENTATONIC: [Note; 5] = [C, D, E, G, A];
 static HARMONIC_MINOR: [Note; 7] = [C, D, DSharp, F, G, GSharp, B];
 static MELODIC_MINOR: [Note; 7] = [C, D, DSharp, F, G, A, B];
-static SUPER_LOCRIAN: [Note; 7] = [C, CSharp, DSharp, E, GSharp, GSharp, ASharp];
+static SUPER_LOCRIAN: [Note; 7] = [C, CSharp, DSharp, E, FSharp, GSharp, ASharp];
 static ARABIC: [Note; 7] = [C, CSharp, E, F, G, GSharp, B];
-static HUNGARIAN_MINOR: [Note; 7] = [C, D, DSharp, GSharp, G, GSharp, B];
+static HUNGARIAN_MINOR: [Note; 7] = [C, D, DSharp, FSharp, G, GSharp, B];
 static MINOR_GYPSY: [Note; 7] = [C, CSharp, E, F, G, GSharp, ASharp];
 static HIROJOSHI: [Note; 5] = [C, D, DSharp, G, GSharp];
 static IN_SEN: [Note; 5] = [C, CSharp, F, G, ASharp];
 static JAPANESE: [Note; 5] = [C, CSharp, F, GSharp, ASharp];
 static KUMOI: [Note; 5] = [C, D, DSharp, G, A];
 static PELOG: [Note; 6] = [C, CSharp, DSharp, E, G, GSharp];
-static SPANISH: [Note; 8] = [C, CSharp, DSharp, E, F, GSharp, GSharp, ASharp];
-static TRITONE: [Note; 6] = [C, CSharp, E, GSharp, G, ASharp];
-static PROMETHEUS: [Note; 6] = [C, D, E, GSharp, A, ASharp];
+static SPANISH: [Note; 8] = [C, CSharp, DSharp, E, F, FSharp, GSharp, ASharp];
+static TRITONE: [Note; 6] = [C, CSharp, E, FSharp, G, ASharp];
+static PROMETHEUS: [Note; 6] = [C, D, E, FSharp, A, ASharp];
 static AUGMENTED: [Note; 6] = [C, DSharp, E, G, GSharp, B];
-static ENIGMATIC: [Note; 7] = [C, CSharp, E, GSharp, GSharp, ASharp, B];
+static ENIGMATIC: [Note; 7] = [C, CSharp, E, FSharp, GSharp, ASharp, B];
 
 impl Scale {
     pub fn notes(self) -> &'static [Note] {
@@ -104,39 +104,14 @@ impl Scale {
         }
     }
 
-    pub fn quantize_float(self, input: f32) -> Note {
-        let notes = self.notes();
-        let max_index = (notes.len() - 1).max(0);
-        let index = F32Ext::round(clamp(input, 0.0, 1.0) * max_index as f32) as usize;
-        notes[index]
+    /// Maps `input` in 0..1 onto the scale's degrees across `octaves` octaves, starting with
+    /// the scale's C on `base`.
+    pub fn quantize_float(self, input: f32, base: Pitch, octaves: u8) -> Pitch {
+        ScaleMask::from(self).quantize_float(input, base, octaves)
     }
 
-    pub fn quantize(self, input: Note) -> Note {
-        let notes = self.notes();
-        let mut min_distance = u8::MAX;
-        let mut output = input;
-        for note in notes {
-            let distance = note.distance(input);
-            if distance == 0 {
-                return input;
-            }
-            if distance < min_distance {
-                min_distance = distance;
-                output = note.clone();
-            }
-        };
-        return output;
-    }
-}
-
-fn clamp(num: f32, min: f32, max: f32) -> f32 {
-    assert!(min <= max);
-    let mut x = num;
-    if x < min {
-        x = min;
-    }
-    if x > max {
-        x = max;
+    /// Returns the nearest pitch in the scale, which may lie in a neighbouring octave.
+    pub fn quantize(self, input: Pitch) -> Pitch {
+        ScaleMask::from(self).quantize(input)
     }
-    x
 }
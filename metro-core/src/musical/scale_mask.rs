@@ -0,0 +1,129 @@
+use micromath::F32Ext;
+
+use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::musical::scale::Scale;
+use crate::tuning::system::{NoteSteps, Tuning};
+
+/// A scale as a set of pitch classes, with bit 0 for C up to bit 11 for B. Any of the 4096
+/// combinations can be used, and the bits can be stored as they are.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ScaleMask(u16);
+
+impl ScaleMask {
+    pub const CHROMATIC: ScaleMask = ScaleMask(0x0FFF);
+
+    /// Bits above B are ignored, and an empty mask keeps just C so there is always a note.
+    pub fn new(bits: u16) -> ScaleMask {
+        match bits & Self::CHROMATIC.0 {
+            0 => ScaleMask(1),
+            bits => ScaleMask(bits),
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(self, note: Note) -> bool {
+        self.0 & (1 << note as u8) > 0
+    }
+
+    /// Adds or removes a note, never leaving the scale empty.
+    pub fn toggle(self, note: Note) -> ScaleMask {
+        ScaleMask::new(self.0 ^ (1 << note as u8))
+    }
+
+    /// Number of notes in the scale.
+    pub fn count(self) -> u8 {
+        self.0.count_ones() as u8
+    }
+
+    /// The scale's `n`th note counting up from C.
+    pub fn nth(self, n: u8) -> Option<Note> {
+        Note::ALL.iter().copied().filter(|&note| self.contains(note)).nth(n as usize)
+    }
+
+    /// Maps `input` in 0..1 onto the scale's degrees across `octaves` octaves, starting with
+    /// the scale's C on `base`.
+    pub fn quantize_float(self, input: f32, base: Pitch, octaves: u8) -> Pitch {
+        self.quantize_float_in(input, base, Note::C, octaves, NoteSteps::EDO_12)
+    }
+
+    /// Returns the nearest pitch in the scale, which may lie in a neighbouring octave.
+    pub fn quantize(self, input: Pitch) -> Pitch {
+        self.quantize_in(input, Note::C, NoteSteps::EDO_12)
+    }
+
+    /// Like `quantize_float`, with the scale starting on `root` and its notes on their `steps`
+    /// in a tuning. The chromatic scale spans every step of the tuning.
+    pub(crate) fn quantize_float_in(self, input: f32, base: Pitch, root: Note, octaves: u8, steps: NoteSteps) -> Pitch {
+        let chromatic = self == Self::CHROMATIC;
+        let len = if chromatic { steps.period() as usize } else { self.count() as usize };
+        let max_index = len * octaves.max(1) as usize - 1;
+        let index = F32Ext::round(input.clamp(0.0, 1.0) * max_index as f32) as usize;
+        let degree = match chromatic {
+            true => (index % len) as i32,
+            false => steps.step(self.nth((index % len) as u8).expect("note should exist")),
+        };
+        let period = (index / len) as i32 * steps.period();
+        base.transpose((steps.step(root) + period + degree) as i16)
+    }
+
+    /// Like `quantize`, with the scale starting on `root` and its notes on their `steps` in a
+    /// tuning, repeating every period from `Tuning::ROOT`. The chromatic scale passes every
+    /// step of the tuning through.
+    pub(crate) fn quantize_in(self, input: Pitch, root: Note, steps: NoteSteps) -> Pitch {
+        if self == Self::CHROMATIC {
+            return input;
+        }
+        let root = Tuning::ROOT.0 as i32 + steps.step(root);
+        let period = (input.0 as i32 - root).div_euclid(steps.period());
+        let mut min_distance = i32::MAX;
+        let mut output = input;
+        for period in period - 1..=period + 1 {
+            for note in Note::ALL.iter().filter(|&&note| self.contains(note)) {
+                let value = root + period * steps.period() + steps.step(*note);
+                if value < Pitch::MIN.0 as i32 || value > Pitch::MAX.0 as i32 {
+                    continue;
+                }
+                let distance = (value - input.0 as i32).abs();
+                if distance < min_distance {
+                    min_distance = distance;
+                    output = Pitch(value as u8);
+                }
+            }
+        }
+        output
+    }
+}
+
+impl From<Scale> for ScaleMask {
+    fn from(scale: Scale) -> ScaleMask {
+        ScaleMask::new(scale.notes().iter().fold(0, |bits, note| bits | 1 << *note as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::note::Note;
+    use crate::musical::pitch::Pitch;
+    use crate::musical::scale::Scale;
+    use crate::musical::scale_mask::ScaleMask;
+
+    #[test]
+    fn test_scale_mask() {
+        assert_eq!(0b_1010_1011_0101, ScaleMask::from(Scale::Major).bits());
+        assert_eq!(ScaleMask::CHROMATIC, ScaleMask::from(Scale::Chromatic));
+        assert_eq!(1, ScaleMask::new(0).bits());
+        assert_eq!(0b_1001, ScaleMask::new(0xF001).toggle(Note::DSharp).bits());
+
+        let custom = ScaleMask::new(0b_0000_1001_0001);
+        assert_eq!(3, custom.count());
+        assert_eq!(Some(Note::G), custom.nth(2));
+        assert_eq!(Pitch(64), custom.quantize(Pitch(65)));
+        assert_eq!(Pitch(72), custom.quantize(Pitch(70)));
+        assert_eq!(Pitch(55), custom.quantize_float(0.4, Pitch(48), 2));
+        assert_eq!(Pitch(67), custom.quantize_float(1.0, Pitch(48), 2));
+    }
+}
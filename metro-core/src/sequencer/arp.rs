@@ -0,0 +1,30 @@
+use crate::musical::chord::Chord;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ArpMode {
+    Up = 0,
+    Down = 1,
+    UpDown = 2,
+    Random = 3,
+    AsPlayed = 4,
+}
+
+impl ArpMode {
+    /// Semitones above the root of the chord tone played on `pulse`.
+    pub fn interval(self, chord: &Chord, pulse: u8, rng: &mut oorandom::Rand32) -> u8 {
+        let len = chord.intervals().len();
+        let pulse = pulse as usize;
+        match self {
+            ArpMode::Up => chord.sorted().intervals()[pulse % len],
+            ArpMode::Down => chord.sorted().intervals()[len - 1 - pulse % len],
+            ArpMode::UpDown if len > 1 => {
+                let cycle = 2 * len - 2;
+                let i = pulse % cycle;
+                chord.sorted().intervals()[if i < len { i } else { cycle - i }]
+            }
+            ArpMode::UpDown => chord.intervals()[0],
+            ArpMode::Random => chord.intervals()[rng.rand_range(0..len as u32) as usize],
+            ArpMode::AsPlayed => chord.intervals()[pulse % len],
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::sequencer::sequencer::Sequencer;
+
+/// Something that advances on clock pulses, like a single sequencer or a set of tracks.
+pub trait Clocked {
+    fn step(&mut self);
+    fn reset(&mut self);
+    fn set_pulse_time_us(&mut self, pulse_time_us: u32);
+    fn set_gate_time_us(&mut self, gate_time_us: u32);
+    fn set_external_clock(&mut self, external_clock: bool);
+}
+
+impl<const N: usize> Clocked for Sequencer<N> {
+    fn step(&mut self) { Sequencer::step(self) }
+
+    fn reset(&mut self) { Sequencer::reset(self) }
+
+    fn set_pulse_time_us(&mut self, pulse_time_us: u32) {
+        self.config().set_pulse_time_us(pulse_time_us)
+    }
+
+    fn set_gate_time_us(&mut self, gate_time_us: u32) {
+        self.config().set_gate_time_us(gate_time_us)
+    }
+
+    fn set_external_clock(&mut self, external_clock: bool) {
+        self.config().set_external_clock(external_clock)
+    }
+}
+
+/// Drives a sequencer from an external clock and reset input, estimating the clock period from
+/// the time between rising edges.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockInput {
+    last_edge_us: Option<u32>,
+    period_us: u32,
+    gate_percent: Option<u8>,
+    reset_pending: bool,
+}
+
+impl ClockInput {
+    /// The first edge is the downbeat and plays the sequencer's current stage.
+    pub fn new() -> Self {
+        Self { last_edge_us: None, period_us: 0, gate_percent: None, reset_pending: true }
+    }
+
+    /// Estimated time between clock edges, or 0 until two edges have been seen.
+    pub fn period_us(&self) -> u32 {
+        self.period_us
+    }
+
+    /// Time since the last clock edge, to pass on to `Sequencer::state`.
+    pub fn last_beat_us(&self, now_us: u32) -> u32 {
+        self.last_edge_us.map_or(0, |edge_us| now_us.wrapping_sub(edge_us))
+    }
+
+    /// When set, the gate time follows the clock as a percentage of its period.
+    pub fn set_gate_percent(&mut self, gate_percent: Option<u8>) {
+        self.gate_percent = gate_percent
+    }
+
+    /// Steps the sequencer, which then takes its timing from this clock instead of swinging it.
+    pub fn rising_edge<C: Clocked>(&mut self, now_us: u32, seq: &mut C) {
+        seq.set_external_clock(true);
+        if let Some(edge_us) = self.last_edge_us {
+            self.estimate(now_us.wrapping_sub(edge_us));
+            seq.set_pulse_time_us(self.period_us);
+            if let Some(percent) = self.gate_percent {
+                seq.set_gate_time_us((self.period_us as u64 * percent as u64 / 100) as u32);
+            }
+        }
+        self.last_edge_us = Some(now_us);
+        if self.reset_pending {
+            self.reset_pending = false;
+        } else {
+            seq.step();
+        }
+    }
+
+    /// Jumps back to the first active stage, which then plays on the next clock edge.
+    pub fn reset<C: Clocked>(&mut self, seq: &mut C) {
+        seq.reset();
+        self.reset_pending = true;
+    }
+
+    /// Averages over the last few edges, but follows tempo jumps and a clock that stopped straight away.
+    fn estimate(&mut self, interval_us: u32) {
+        let period_us = self.period_us;
+        self.period_us = if period_us == 0 || interval_us > period_us * 2 || interval_us < period_us / 2 {
+            interval_us
+        } else {
+            ((period_us as u64 * 3 + interval_us as u64) / 4) as u32
+        };
+    }
+}
+
+impl Default for ClockInput {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::gate::Gate;
+    use crate::sequencer::clock::ClockInput;
+    use crate::sequencer::sequencer::Sequencer;
+
+    #[test]
+    fn test_clock_input() {
+        let mut seq: Sequencer = Sequencer::new();
+        let mut clock = ClockInput::new();
+        clock.set_gate_percent(Some(50));
+
+        clock.rising_edge(1_000, &mut seq);
+        assert_eq!(0, clock.period_us());
+        assert_eq!(0, seq.state(0).pos.stage);
+
+        clock.rising_edge(11_000, &mut seq);
+        clock.rising_edge(23_000, &mut seq);
+        assert_eq!(10_500, clock.period_us());
+        assert_eq!(2_000, clock.last_beat_us(25_000));
+        assert_eq!(2, seq.state(0).pos.stage);
+        assert_eq!(10_500, seq.next_pulse_us());
+
+        clock.rising_edge(123_000, &mut seq);
+        assert_eq!(100_000, clock.period_us());
+
+        clock.reset(&mut seq);
+        assert_eq!(0, seq.state(0).pos.stage);
+        clock.rising_edge(223_000, &mut seq);
+        assert_eq!(0, seq.state(0).pos.stage);
+        clock.rising_edge(323_000, &mut seq);
+        assert_eq!(1, seq.state(0).pos.stage);
+    }
+
+    #[test]
+    fn test_clock_input_swing() {
+        let mut seq: Sequencer = Sequencer::new();
+        let mut clock = ClockInput::new();
+        seq.config().set_swing(50);
+        seq.config().set_gate_time_us(500);
+        seq.config().stage(1).unwrap().ratchets = 2;
+
+        clock.rising_edge(0, &mut seq);
+        clock.rising_edge(1_000, &mut seq);
+        assert_eq!(1_000, seq.next_pulse_us());
+        assert_eq!(Gate::Open, seq.state(200).gate);
+        assert_eq!(Gate::Closed, seq.state(300).gate);
+        assert_eq!(Gate::Open, seq.state(500).gate);
+
+        clock.rising_edge(2_000, &mut seq);
+        assert_eq!(1_000, seq.next_pulse_us());
+        assert_eq!(Gate::Open, seq.state(400).gate);
+        assert_eq!(Gate::Closed, seq.state(600).gate);
+    }
+}
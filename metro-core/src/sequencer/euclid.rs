@@ -0,0 +1,68 @@
+use crate::sequencer::sequencer::{Config, MaskU32};
+
+/// Spreads `hits` as evenly as possible over `steps`, shifted later by `rotation` steps.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Euclid {
+    hits: u8,
+    steps: u8,
+    rotation: u8,
+}
+
+impl Euclid {
+    pub fn new(hits: u8, steps: u8, rotation: u8) -> Self {
+        let steps = steps.clamp(1, MaskU32::BITS);
+        Self { hits: hits.min(steps), steps, rotation: rotation % steps }
+    }
+
+    pub fn hits(&self) -> u8 { self.hits }
+
+    pub fn steps(&self) -> u8 { self.steps }
+
+    pub fn rotation(&self) -> u8 { self.rotation }
+
+    pub fn is_hit(&self, index: u8) -> bool {
+        let steps = self.steps as u32;
+        let i = (index as u32 % steps + steps - self.rotation as u32) % steps;
+        (i * self.hits as u32) % steps < self.hits as u32
+    }
+
+    /// One bit per step of a single cycle, set where there is a hit.
+    pub fn mask(&self) -> MaskU32 {
+        MaskU32((0..self.steps).filter(|&i| self.is_hit(i)).fold(0, |mask, i| mask | 1 << i))
+    }
+
+    /// Only stages on a hit keep their gates; stage settings are left alone. The rhythm repeats
+    /// over all stages, counted from the start of the loop window, so it needs applying again
+    /// after the window moves.
+    pub fn apply<const N: usize>(&self, config: &mut Config<N>) {
+        let (start, _) = config.loop_window();
+        let mask = (0..N as u8)
+            .filter(|&stage| self.is_hit(((stage as usize + N - start as usize) % N) as u8))
+            .fold(0, |mask, stage| mask | 1 << stage);
+        config.set_gate_mask(MaskU32(mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sequencer::euclid::Euclid;
+    use crate::sequencer::sequencer::Config;
+
+    #[test]
+    fn test_euclid() {
+        assert_eq!(0b_0100_1001, Euclid::new(3, 8, 0).mask().0);
+        assert_eq!(0b_1001_0010, Euclid::new(3, 8, 1).mask().0);
+        assert_eq!(0b_1011_0101, Euclid::new(5, 8, 0).mask().0);
+        assert_eq!(0b_1111, Euclid::new(9, 4, 0).mask().0);
+        assert_eq!(0, Euclid::new(0, 16, 3).mask().0);
+        assert!(Euclid::new(4, 16, 2).is_hit(18));
+
+        let mut config: Config<16> = Config::new();
+        Euclid::new(2, 4, 0).apply(&mut config);
+        assert_eq!(0x5555, config.gate_mask().0);
+        config.set_loop(3, 10);
+        Euclid::new(2, 4, 0).apply(&mut config);
+        assert_eq!(0b_1010_1010_1010_1010, config.gate_mask().0);
+        assert!(!config.gate_mask().is_set(4));
+    }
+}
@@ -1,2 +1,11 @@
+pub mod arp;
+pub mod clock;
+pub mod euclid;
+#[allow(clippy::module_inception)]
 pub mod sequencer;
+pub mod pattern;
 pub mod stage_mode;
+pub mod step_script;
+pub mod trig_condition;
+pub mod tracks;
+pub mod turing;
@@ -0,0 +1,204 @@
+use crate::musical::key::Key;
+use crate::sequencer::sequencer::{Config, DEFAULT_STAGES, Sequencer, Stage};
+use crate::sequencer::stage_mode::StageMode;
+
+/// Everything that makes up one pattern: its stages and how they are played.
+#[derive(Debug, Clone, Copy)]
+pub struct Pattern<const N: usize = DEFAULT_STAGES> {
+    pub stages: [Stage; N],
+    pub stage_mode: StageMode,
+    pub length: u8,
+    pub gate_time_us: u32,
+    pub key: Key,
+}
+
+impl<const N: usize> Pattern<N> {
+    pub fn new() -> Self {
+        Self::from_config(&Config::new())
+    }
+
+    pub fn from_config(config: &Config<N>) -> Self {
+        let mut stages = [Stage::default(); N];
+        stages.copy_from_slice(config.stages());
+        Self { stages, stage_mode: config.stage_mode(), length: config.length(), gate_time_us: config.gate_time_us(), key: config.key() }
+    }
+
+    pub fn apply(&self, config: &mut Config<N>) {
+        for (i, stage) in self.stages.iter().enumerate() {
+            *config.stage(i).expect("stage should exist") = *stage;
+        }
+        config.set_stage_mode(self.stage_mode);
+        config.set_length(self.length);
+        config.set_gate_time_us(self.gate_time_us);
+        config.set_key(self.key);
+    }
+}
+
+impl<const N: usize> Default for Pattern<N> {
+    fn default() -> Self { Self::new() }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ChainEntry {
+    pub pattern: u8,
+    pub repeats: u8,
+}
+
+/// Holds `P` patterns and an optional chain of up to `C` entries that is played as a song.
+/// Pattern changes only ever happen at loop boundaries.
+#[derive(Debug, Clone)]
+pub struct Bank<const P: usize, const N: usize = DEFAULT_STAGES, const C: usize = 16> {
+    patterns: [Pattern<N>; P],
+    chain: [ChainEntry; C],
+    chain_len: u8,
+    chain_pos: u8,
+    repeats: u8,
+    current: u8,
+    queued: Option<u8>,
+}
+
+impl<const P: usize, const N: usize, const C: usize> Bank<P, N, C> {
+    pub fn new() -> Self {
+        Self {
+            patterns: [Pattern::new(); P],
+            chain: [ChainEntry { pattern: 0, repeats: 1 }; C],
+            chain_len: 0,
+            chain_pos: 0,
+            repeats: 0,
+            current: 0,
+            queued: None,
+        }
+    }
+
+    pub fn pattern(&mut self, index: usize) -> Option<&mut Pattern<N>> {
+        self.patterns.get_mut(index)
+    }
+
+    pub fn current(&self) -> u8 {
+        self.current
+    }
+
+    /// Saves the sequencer's live edits into the pattern slot at `index`.
+    pub fn store(&mut self, index: usize, seq: &mut Sequencer<N>) {
+        if let Some(pattern) = self.patterns.get_mut(index) {
+            *pattern = Pattern::from_config(seq.config());
+        }
+    }
+
+    /// Replaces the song chain; entries pointing at missing patterns are dropped.
+    pub fn set_chain(&mut self, chain: &[ChainEntry]) {
+        self.chain_len = 0;
+        for entry in chain.iter().filter(|e| (e.pattern as usize) < P).take(C) {
+            self.chain[self.chain_len as usize] = *entry;
+            self.chain_len += 1;
+        }
+        self.chain_pos = 0;
+        self.repeats = 0;
+    }
+
+    pub fn chain(&self) -> &[ChainEntry] {
+        &self.chain[..self.chain_len as usize]
+    }
+
+    /// Switches to `pattern` at the next loop boundary, ahead of the chain.
+    pub fn queue(&mut self, pattern: u8) {
+        if (pattern as usize) < P {
+            self.queued = Some(pattern)
+        }
+    }
+
+    /// Loads the first chain entry, or the current pattern without a chain, and starts from the top.
+    pub fn start(&mut self, seq: &mut Sequencer<N>) {
+        self.chain_pos = 0;
+        self.repeats = 0;
+        let pattern = self.chain().first().map_or(self.current, |e| e.pattern);
+        self.load(pattern, seq);
+    }
+
+    /// Steps the sequencer and moves through the queue or chain when it completes a loop.
+    pub fn step(&mut self, seq: &mut Sequencer<N>) {
+        let iteration = seq.iteration();
+        seq.step();
+        if seq.iteration() != iteration {
+            self.on_loop(seq);
+        }
+    }
+
+    fn on_loop(&mut self, seq: &mut Sequencer<N>) {
+        if let Some(pattern) = self.queued.take() {
+            self.load(pattern, seq);
+            return;
+        }
+        if self.chain_len == 0 {
+            return;
+        }
+        self.repeats += 1;
+        if self.repeats >= self.chain[self.chain_pos as usize].repeats {
+            self.repeats = 0;
+            self.chain_pos = (self.chain_pos + 1) % self.chain_len;
+            self.load(self.chain[self.chain_pos as usize].pattern, seq);
+        }
+    }
+
+    fn load(&mut self, pattern: u8, seq: &mut Sequencer<N>) {
+        self.current = pattern;
+        self.patterns[pattern as usize].apply(seq.config());
+        seq.reset();
+    }
+}
+
+impl<const P: usize, const N: usize, const C: usize> Default for Bank<P, N, C> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::pitch::Pitch;
+    use crate::sequencer::pattern::{Bank, ChainEntry, Pattern};
+    use crate::sequencer::sequencer::Sequencer;
+
+    #[test]
+    fn test_chain() {
+        let mut seq: Sequencer<4> = Sequencer::new();
+        let mut bank: Bank<3, 4> = Bank::new();
+        for p in 0..3 {
+            bank.pattern(p).unwrap().stages[0].note = Pitch(60 + p as u8);
+        }
+        bank.set_chain(&[ChainEntry { pattern: 1, repeats: 2 }, ChainEntry { pattern: 2, repeats: 1 }, ChainEntry { pattern: 7, repeats: 1 }]);
+        assert_eq!(2, bank.chain().len());
+        bank.start(&mut seq);
+        assert_eq!(Pitch(61), seq.state(0).note);
+
+        let mut loops = [0_u8; 5];
+        for pattern in loops.iter_mut() {
+            for _ in 0..4 {
+                bank.step(&mut seq);
+            }
+            *pattern = bank.current();
+        }
+        assert_eq!([1, 2, 1, 1, 2], loops);
+        assert_eq!(Pitch(62), seq.state(0).note);
+
+        bank.queue(0);
+        bank.step(&mut seq);
+        assert_eq!(2, bank.current());
+        for _ in 0..3 {
+            bank.step(&mut seq);
+        }
+        assert_eq!(0, bank.current());
+        assert_eq!(0, seq.state(0).pos.stage);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let mut seq: Sequencer<4> = Sequencer::new();
+        seq.config().set_length(3);
+        let short = Pattern::from_config(seq.config());
+        assert_eq!(3, short.length);
+
+        Pattern::new().apply(seq.config());
+        assert_eq!(4, seq.config().length());
+        short.apply(seq.config());
+        assert_eq!(3, seq.config().length());
+    }
+}
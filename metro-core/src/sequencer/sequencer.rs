@@ -1,45 +1,243 @@
 use oorandom;
 
+use crate::modulation::envelope::Envelope;
+use crate::musical::chord::Chord;
 use crate::musical::gate::Gate;
+use crate::musical::key::Key;
 use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::musical::scale::Scale;
+use crate::sequencer::arp::ArpMode;
 use crate::sequencer::stage_mode::StageMode;
+use crate::sequencer::trig_condition::TrigCondition;
+use crate::tuning::system::{NoteSteps, Tuning};
 
-const N: usize = 8;
+/// Stage count used when a `Sequencer` or `Config` is named without one.
+pub const DEFAULT_STAGES: usize = 8;
 
 #[derive(Debug, Clone)]
-pub struct Sequencer {
-    config: Config,
+pub struct Sequencer<const N: usize = DEFAULT_STAGES> {
+    config: Config<N>,
     pos: Position,
+    prev_note: Pitch,
+    prev_cv: f32,
+    arp_interval: u8,
+    /// Whether the current stage fires on this pass. Left undecided after a reset until the
+    /// first step, so settings made in the meantime still count for the first stage.
+    fired: Option<bool>,
+    iteration: u32,
+    loop_stage: u8,
+    fill: bool,
+    pre: bool,
+    tick: u32,
 }
 
-impl Sequencer {
-    pub fn new() -> Sequencer {
-        Self { pos: Position { stage: 0, pulse: 0, dir: Direction::Forward }, config: Config::new() }
+impl<const N: usize> Sequencer<N> {
+    pub fn new() -> Self {
+        let mut seq = Self {
+            pos: Position { stage: 0, pulse: 0, dir: Direction::Forward, played: MaskU32::new(), step: 0 },
+            config: Config::new(),
+            prev_note: Pitch::from(Note::C),
+            prev_cv: 0.0,
+            arp_interval: 0,
+            fired: None,
+            iteration: 0,
+            loop_stage: 0,
+            fill: false,
+            pre: false,
+            tick: 0,
+        };
+        seq.reset();
+        seq
     }
 
-    pub fn config(&mut self) -> &mut Config {
+    pub fn config(&mut self) -> &mut Config<N> {
         &mut self.config
     }
 
+    /// Number of completed loops through the stage mode's order.
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn set_fill(&mut self, fill: bool) {
+        self.fill = fill
+    }
+
+    /// Time from the current pulse until the next one is due. With swing, pulses off the grid
+    /// are delayed, so the pulse before them runs long and the one after runs short.
+    pub fn next_pulse_us(&self) -> u32 {
+        let pulse_time_us = self.config.pulse_time_us;
+        if self.config.external_clock {
+            return pulse_time_us;
+        }
+        let delay_us = (pulse_time_us as u64 * self.config.swing as u64 / 100) as u32;
+        match self.tick % 2 {
+            0 => pulse_time_us + delay_us,
+            _ => pulse_time_us - delay_us,
+        }
+    }
+
     pub fn state(&self, last_beat_us: u32) -> State {
         let current_stage = self.stage(self.pos).expect("stage should exist");
-        let gate = current_stage.gate_mode.gate(self.config.gate_time_us, last_beat_us, self.pos.pulse == 0, self.pos.pulse > current_stage.pulse_count - 1);
-        State { gate, note: current_stage.note, pos: self.pos }
+        let (gate_length, pulse_time_us, last_trigger_us) = self.ratchet_timing(current_stage, last_beat_us);
+        // Until the first step decides it, preview the roll that step will make on a copy of the rng
+        let fired = self.fired.unwrap_or_else(|| {
+            let mut rng = self.config.rng;
+            self.evaluate_trig(&mut rng).0
+        });
+        let gate = match fired && self.config.gate_mask.is_set(self.pos.stage) {
+            true => current_stage.gate_mode.gate(gate_length, pulse_time_us, last_trigger_us, self.pos.pulse == 0, self.pos.pulse > current_stage.pulse_count - 1),
+            false => Gate::Closed,
+        };
+        let accent = current_stage.accent.accent(gate, self.pos.pulse == 0);
+        let note = self.note(current_stage);
+        let pitch = match current_stage.slide {
+            true => self.slide(current_stage, self.prev_note.0 as f32, note.0 as f32, last_beat_us),
+            false => note.0 as f32,
+        };
+        let cv = match current_stage.cv_slide {
+            true => self.slide(current_stage, self.prev_cv, current_stage.cv, last_beat_us),
+            false => current_stage.cv,
+        };
+        let voltage = self.config.tuning.voltage(pitch);
+        State { gate, accent, note, pitch, voltage, cv, pos: self.pos }
     }
 
     pub fn step(&mut self) {
         if !self.config.has_pulses() { return; }
+        self.tick = self.tick.wrapping_add(1);
+        if self.fired.is_none() {
+            self.fired = Some(self.trig());
+        }
 
         let current_stage = self.stage(self.pos).expect("stage should exist");
         if self.pos.pulse < current_stage.pulse_count - 1 && !current_stage.skipped {
-            self.pos = Position { stage: self.pos.stage, pulse: self.pos.pulse + 1, dir: self.pos.dir }
+            self.pos = Position { pulse: self.pos.pulse + 1, ..self.pos }
         } else {
-            self.pos = self.next_stage_pos(self.pos)
+            let prev_cv = current_stage.cv;
+            self.prev_note = self.note(current_stage);
+            self.prev_cv = prev_cv;
+            self.pos = self.next_stage_pos(self.pos);
+            self.advance_loop();
+            self.fired = Some(self.trig());
+        }
+        self.arp_interval = self.arp_interval();
+    }
+
+    /// Moves back to the first active stage and restarts pulse and loop counting.
+    pub fn reset(&mut self) {
+        let mask = self.config.has_pulses_mask();
+        self.pos = match self.config.stage_mode {
+            StageMode::Reverse => Position { stage: mask.highest().unwrap_or(0), pulse: 0, dir: Direction::Reverse, played: MaskU32::new(), step: 0 },
+            StageMode::Script(script) => {
+                let (step, stage) = script.first(mask).unwrap_or((0, 0));
+                Position { stage, pulse: 0, dir: Direction::Forward, played: MaskU32::new(), step }
+            }
+            StageMode::Diverge => {
+                let stage = mask.nth(mask.count().saturating_sub(1) / 2).unwrap_or(0);
+                Position { stage, pulse: 0, dir: Direction::Forward, played: MaskU32::new(), step: 0 }
+            }
+            _ => Position { stage: mask.lowest().unwrap_or(0), pulse: 0, dir: Direction::Forward, played: MaskU32::new(), step: 0 },
+        };
+        self.tick = 0;
+        self.iteration = 0;
+        self.loop_stage = 0;
+        self.fired = None;
+        self.arp_interval = self.arp_interval();
+        // A first stage that slides starts from its own note rather than gliding in.
+        let stage = *self.stage(self.pos).expect("stage should exist");
+        self.prev_note = self.note(&stage);
+        self.prev_cv = stage.cv;
+    }
+
+    /// Splits the current pulse into the stage's ratchets and returns the gate length, the time
+    /// of a sub-pulse and the time since the last sub-trigger. Ratchet gates are capped at half a
+    /// sub-pulse so retriggers stay audible.
+    fn ratchet_timing(&self, stage: &Stage, last_beat_us: u32) -> (GateLength, u32, u32) {
+        let pulse_time_us = self.next_pulse_us();
+        let gate_length = stage.gate_length.unwrap_or(GateLength::Time(self.swung_gate_time_us(pulse_time_us)));
+        let ratchets = stage.ratchets.clamp(1, Stage::MAX_RATCHETS) as u32;
+        if ratchets == 1 || pulse_time_us == 0 {
+            return (gate_length, pulse_time_us, last_beat_us);
         }
+        let sub_pulse_us = (pulse_time_us / ratchets).max(1);
+        let sub_pulse = (last_beat_us / sub_pulse_us).min(ratchets - 1);
+        let gate_time_us = gate_length.time_us(sub_pulse_us).min(sub_pulse_us / 2);
+        (GateLength::Time(gate_time_us), sub_pulse_us, last_beat_us - sub_pulse * sub_pulse_us)
     }
 
-    fn next_stage_pos(&self, pos: Position) -> Position {
-        self.config.stage_mode.next_stage(self.config.has_pulses_mask(), pos, self.config.rng)
+    /// Stretches or shrinks the gate time with the swung pulse so gates keep their share of the pulse.
+    fn swung_gate_time_us(&self, pulse_time_us: u32) -> u32 {
+        match self.config.pulse_time_us {
+            0 => self.config.gate_time_us,
+            nominal_us => (self.config.gate_time_us as u64 * pulse_time_us as u64 / nominal_us as u64) as u32,
+        }
+    }
+
+    /// Glides linearly from the previous stage's value to the current one over the stage's slide time.
+    fn slide(&self, stage: &Stage, from: f32, target: f32, last_beat_us: u32) -> f32 {
+        if stage.slide_time_us == 0 {
+            return target;
+        }
+        let elapsed_us = self.pos.pulse as u32 * self.config.pulse_time_us + last_beat_us;
+        if elapsed_us >= stage.slide_time_us {
+            return target;
+        }
+        from + (target - from) * (elapsed_us as f32 / stage.slide_time_us as f32)
+    }
+
+    /// The stage's note, raised to the current arp tone, quantized to the config's key and tuning.
+    fn note(&self, stage: &Stage) -> Pitch {
+        let steps = self.config.note_steps;
+        self.config.key.quantize_in(stage.note.transpose(steps.interval(self.arp_interval) as i16), steps)
+    }
+
+    fn arp_interval(&mut self) -> u8 {
+        let stage = *self.stage(self.pos).expect("stage should exist");
+        match stage.chord {
+            Some(chord) => stage.arp.interval(&chord, self.pos.pulse, &mut self.config.rng),
+            None => 0,
+        }
+    }
+
+    fn next_stage_pos(&mut self, pos: Position) -> Position {
+        let mask = self.config.has_pulses_mask();
+        self.config.stage_mode.next_stage(mask, pos, &mut self.config.rng)
+    }
+
+    /// Counts an iteration whenever the stage mode wraps back to its start. The random modes
+    /// have no start, so for them an iteration is as many stages as are active.
+    fn advance_loop(&mut self) {
+        let mask = self.config.has_pulses_mask();
+        let wrapped = match self.config.stage_mode.starts_cycle(mask, self.pos) {
+            Some(wrapped) => wrapped,
+            None => {
+                self.loop_stage += 1;
+                self.loop_stage >= mask.count()
+            }
+        };
+        if wrapped {
+            self.loop_stage = 0;
+            self.iteration = self.iteration.wrapping_add(1);
+        }
+    }
+
+    /// Decides whether the stage just entered fires its gate on this pass.
+    fn trig(&mut self) -> bool {
+        let mut rng = self.config.rng;
+        let (fired, pre) = self.evaluate_trig(&mut rng);
+        self.config.rng = rng;
+        self.pre = pre;
+        fired
+    }
+
+    /// Whether the current stage fires, and the resulting Pre state, without changing anything.
+    fn evaluate_trig(&self, rng: &mut oorandom::Rand32) -> (bool, bool) {
+        let stage = self.stage(self.pos).expect("stage should exist");
+        let condition = stage.condition.evaluate(self.iteration, self.fill, self.pre);
+        let pre = if stage.condition.sets_pre() { condition } else { self.pre };
+        (condition && roll_probability(stage.probability, rng), pre)
     }
 
     fn stage(&self, pos: Position) -> Option<&Stage> {
@@ -47,11 +245,23 @@ impl Sequencer {
     }
 }
 
+fn roll_probability(probability: u8, rng: &mut oorandom::Rand32) -> bool {
+    probability >= Stage::MAX_PROBABILITY || rng.rand_range(0..Stage::MAX_PROBABILITY as u32) < probability as u32
+}
+
+impl<const N: usize> Default for Sequencer<N> {
+    fn default() -> Self { Self::new() }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Position {
     pub stage: u8,
     pub pulse: u8,
     pub dir: Direction,
+    /// Stages already played in this round of `StageMode::Shuffle`.
+    pub played: MaskU32,
+    /// Index into the script of `StageMode::Script`.
+    pub step: u8,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -70,17 +280,45 @@ impl Direction {
 }
 
 #[derive(Debug, Clone)]
-pub struct Config {
+pub struct Config<const N: usize = DEFAULT_STAGES> {
     stages: [Stage; N],
     stage_mode: StageMode,
+    key: Key,
+    tuning: Tuning,
+    note_steps: NoteSteps,
+    length: u8,
+    loop_start: u8,
+    loop_end: u8,
+    gate_mask: MaskU32,
     gate_time_us: u32,
+    pulse_time_us: u32,
+    swing: u8,
+    external_clock: bool,
     rng: oorandom::Rand32,
 }
 
-impl Config
+impl<const N: usize> Config<N>
 {
+    pub const MAX_SWING: u8 = 75;
+
     pub fn new() -> Self {
-        Self { stages: [Stage::default(); N], stage_mode: StageMode::Forward, gate_time_us: 50, rng: oorandom::Rand32::new(0) }
+        const { assert!(N > 0 && N <= MaskU32::BITS as usize, "stage count must fit in a MaskU32") };
+        Self {
+            stages: [Stage::default(); N],
+            stage_mode: StageMode::Forward,
+            key: Key::from(Scale::Chromatic),
+            tuning: Tuning::default(),
+            note_steps: NoteSteps::EDO_12,
+            length: N as u8,
+            loop_start: 0,
+            loop_end: N as u8 - 1,
+            gate_mask: MaskU32(u32::MAX),
+            gate_time_us: 50,
+            pulse_time_us: 0,
+            swing: 0,
+            external_clock: false,
+            rng: oorandom::Rand32::new(0),
+        }
     }
 
     pub fn stage(&mut self, index: usize) -> Option<&mut Stage> {
@@ -89,56 +327,125 @@ impl Config
 
     pub fn stages(&self) -> &[Stage] { &self.stages }
 
-    pub fn has_pulses(&self) -> bool { self.stages.iter().any(|s| s.has_pulses()) }
+    pub fn stage_mode(&self) -> StageMode { self.stage_mode }
+
+    pub fn key(&self) -> Key { self.key }
+
+    pub fn tuning(&self) -> Tuning { self.tuning }
+
+    /// Where the key's notes fall in the tuning, worked out once when the tuning is set.
+    pub fn note_steps(&self) -> NoteSteps { self.note_steps }
+
+    pub fn gate_time_us(&self) -> u32 { self.gate_time_us }
+
+    pub fn gate_mask(&self) -> MaskU32 { self.gate_mask }
+
+    pub fn length(&self) -> u8 { self.length }
 
-    pub fn has_pulses_mask(&self) -> MaskU8 {
-        let mut mask = 0_u8;
-        for i in 0..N {
-            if self.stages[i].has_pulses() {
+    pub fn has_pulses(&self) -> bool { self.has_pulses_mask().0 > 0 }
+
+    pub fn has_pulses_mask(&self) -> MaskU32 {
+        let mut mask = 0_u32;
+        for (i, stage) in self.stages.iter().take(self.length as usize).enumerate() {
+            if stage.has_pulses() && self.in_loop(i as u8) {
                 mask |= 1 << i
             }
         };
-        MaskU8(mask)
+        MaskU32(mask)
+    }
+
+    /// Stages outside the mask keep their gates closed.
+    pub fn set_gate_mask(&mut self, gate_mask: MaskU32) {
+        self.gate_mask = gate_mask
     }
 
     pub fn set_gate_time_us(&mut self, gate_time_us: u32) {
         self.gate_time_us = gate_time_us
     }
 
+    /// Sets the expected time between pulses, used to spread ratchets over a pulse.
+    pub fn set_pulse_time_us(&mut self, pulse_time_us: u32) {
+        self.pulse_time_us = pulse_time_us
+    }
+
+    /// Delays every other pulse by `swing` percent of the pulse time.
+    pub fn set_swing(&mut self, swing: u8) {
+        self.swing = swing.min(Self::MAX_SWING)
+    }
+
+    /// An external clock places every pulse itself, so swing no longer changes pulse or gate times.
+    pub fn set_external_clock(&mut self, external_clock: bool) {
+        self.external_clock = external_clock
+    }
+
     pub fn set_stage_mode(&mut self, stage_mode: StageMode) {
         self.stage_mode = stage_mode
     }
 
+    /// Notes are quantized to the key on output; the stages keep their own notes.
+    pub fn set_key(&mut self, key: Key) {
+        self.key = key
+    }
+
+    /// Stage notes count steps of the tuning, which the key is quantized against.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        self.note_steps = tuning.note_steps();
+    }
+
+    /// Only the first `length` stages take part in the sequence.
+    pub fn set_length(&mut self, length: u8) {
+        self.length = length.clamp(1, N as u8)
+    }
+
+    pub fn loop_window(&self) -> (u8, u8) { (self.loop_start, self.loop_end) }
+
+    /// Restricts playback to the stages from `start` to `end`, both included. Stages outside
+    /// the window keep their settings.
+    pub fn set_loop(&mut self, start: u8, end: u8) {
+        let last = N as u8 - 1;
+        self.loop_start = start.min(end).min(last);
+        self.loop_end = start.max(end).min(last);
+    }
+
+    /// Moves the loop window by `offset` stages while keeping its size.
+    pub fn shift_loop(&mut self, offset: i8) {
+        let size = self.loop_end - self.loop_start;
+        let start = (self.loop_start as i16 + offset as i16).clamp(0, (N as u8 - 1 - size) as i16) as u8;
+        self.set_loop(start, start + size);
+    }
+
+    fn in_loop(&self, stage: u8) -> bool {
+        (self.loop_start..=self.loop_end).contains(&stage)
+    }
+
     pub fn set_rnd_seed(&mut self, rnd_seed: u32) { self.rng = oorandom::Rand32::new(rnd_seed as u64) }
+
+    pub(crate) fn rng(&mut self) -> &mut oorandom::Rand32 { &mut self.rng }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct MaskU8(pub u8);
+impl<const N: usize> Default for Config<N> {
+    fn default() -> Self { Self::new() }
+}
 
-impl MaskU8 {
-    pub fn new() -> MaskU8 { Self(0) }
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaskU32(pub u32);
+
+impl MaskU32 {
+    pub const BITS: u8 = 32;
+
+    pub fn new() -> MaskU32 { Self(0) }
 
     pub fn next_higher(&self, pos: u8) -> Option<u8> {
-        for i in pos + 1..8 {
-            if self.0 & (1 << i) > 0 {
-                return Some(i);
-            }
-        };
-        None
+        (pos.saturating_add(1)..Self::BITS).find(|&i| self.is_set(i))
     }
 
     pub fn next_lower(&self, pos: u8) -> Option<u8> {
-        if pos == 0 { return None; }
-        for i in (0..pos).rev() {
-            if self.0 & (1 << i) > 0 {
-                return Some(i);
-            }
-        };
-        None
+        (0..pos.min(Self::BITS)).rev().find(|&i| self.is_set(i))
     }
 
     pub fn highest(&self) -> Option<u8> {
-        self.next_lower(8)
+        self.next_lower(Self::BITS)
     }
 
     pub fn lowest(&self) -> Option<u8> {
@@ -149,8 +456,18 @@ impl MaskU8 {
         }
     }
 
+    /// Position of the `n`th set bit, counting from zero.
+    pub fn nth(&self, n: u8) -> Option<u8> {
+        (0..Self::BITS).filter(|&i| self.is_set(i)).nth(n as usize)
+    }
+
+    /// Number of set bits below `pos`.
+    pub fn rank(&self, pos: u8) -> u8 {
+        MaskU32(self.0 & ((1_u64 << pos.min(Self::BITS)) - 1) as u32).count()
+    }
+
     pub fn is_set(&self, pos: u8) -> bool {
-        self.0 & (1 << pos) > 0
+        pos < Self::BITS && self.0 & (1 << pos) > 0
     }
 
     pub fn count(&self) -> u8 {
@@ -158,7 +475,7 @@ impl MaskU8 {
         let mut bits = self.0;
         while bits > 0 {
             bits = bits & (bits - 1);
-            count = count + 1;
+            count += 1;
         }
         count
     }
@@ -166,8 +483,16 @@ impl MaskU8 {
 
 #[derive(Debug, Clone, Copy)]
 pub struct State {
-    pub note: Note,
+    pub note: Pitch,
+    /// Pitch in steps of the config's tuning (MIDI note numbers in 12-EDO), fractional while a
+    /// stage slides in from the previous one.
+    pub pitch: f32,
+    /// 1V/oct voltage of `pitch` under the config's tuning.
+    pub voltage: f32,
     pub gate: Gate,
+    pub accent: Gate,
+    /// The stage's free CV value in 0..1, interpolated while the stage slews it in.
+    pub cv: f32,
     pub pos: Position,
 }
 
@@ -180,7 +505,8 @@ pub enum GateMode {
 }
 
 impl GateMode {
-    pub fn gate(self, gate_time_us: u32, last_beat_us: u32, first_pulse: bool, last_pulse: bool) -> Gate {
+    pub fn gate(self, length: GateLength, pulse_time_us: u32, last_beat_us: u32, first_pulse: bool, last_pulse: bool) -> Gate {
+        let gate_time_us = length.time_us(pulse_time_us);
         match self {
             GateMode::Repeat if gate_time_us >= last_beat_us => Gate::Open,
             GateMode::Single if gate_time_us >= last_beat_us && first_pulse => Gate::Open,
@@ -190,18 +516,80 @@ impl GateMode {
     }
 }
 
+/// How long a stage's gate stays open on each pulse.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GateLength {
+    /// Share of the pulse period in percent, which keeps up with tempo changes. Needs the pulse
+    /// time to be set.
+    Percent(u8),
+    /// Absolute time in microseconds.
+    Time(u32),
+}
+
+impl GateLength {
+    pub const MAX_PERCENT: u8 = 100;
+
+    pub fn time_us(self, pulse_time_us: u32) -> u32 {
+        match self {
+            GateLength::Percent(percent) => (pulse_time_us as u64 * percent.min(Self::MAX_PERCENT) as u64 / 100) as u32,
+            GateLength::Time(time_us) => time_us,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AccentMode {
+    Off,
+    First,
+    All,
+}
+
+impl AccentMode {
+    /// The accent only ever opens together with the stage's gate.
+    pub fn accent(self, gate: Gate, first_pulse: bool) -> Gate {
+        match self {
+            AccentMode::All if gate == Gate::Open => Gate::Open,
+            AccentMode::First if gate == Gate::Open && first_pulse => Gate::Open,
+            _ => Gate::Closed,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Stage {
-    pub note: Note,
+    pub note: Pitch,
+    /// When set, the stage's pulses step through this chord built on `note`.
+    pub chord: Option<Chord>,
+    pub arp: ArpMode,
     pub pulse_count: u8,
     pub gate_mode: GateMode,
+    /// Overrides the config's gate time for this stage.
+    pub gate_length: Option<GateLength>,
+    pub accent: AccentMode,
+    pub ratchets: u8,
+    /// Chance in percent that the stage's gate fires on each pass.
+    pub probability: u8,
+    pub condition: TrigCondition,
+    pub slide: bool,
+    pub slide_time_us: u32,
+    /// Free CV value in 0..1, for velocity, filter cutoff or timbre.
+    pub cv: f32,
+    /// Slews `cv` in from the previous stage's value over `slide_time_us`.
+    pub cv_slide: bool,
+    /// Times of the envelope the stage's gate triggers on the modulation output.
+    pub envelope: Envelope,
     pub skipped: bool,
 }
 
-impl Stage {
-    pub fn default() -> Stage {
-        Stage { note: Note::C, pulse_count: 1, gate_mode: GateMode::Repeat, skipped: false }
+impl Default for Stage {
+    fn default() -> Stage {
+        Stage { note: Pitch::from(Note::C), chord: None, arp: ArpMode::Up, pulse_count: 1, gate_mode: GateMode::Repeat, gate_length: None, accent: AccentMode::Off, ratchets: 1, probability: 100, condition: TrigCondition::Always, slide: false, slide_time_us: 0, cv: 0.0, cv_slide: false, envelope: Envelope::default(), skipped: false }
     }
+}
+
+impl Stage {
+    pub const MAX_RATCHETS: u8 = 8;
+    pub const MAX_PROBABILITY: u8 = 100;
 
     pub fn has_pulses(&self) -> bool {
         self.pulse_count > 0 && !self.skipped
@@ -210,14 +598,370 @@ impl Stage {
 
 #[cfg(test)]
 mod tests {
+    use crate::musical::chord::{Chord, ChordType};
     use crate::musical::gate::Gate;
+    use crate::musical::key::Key;
+    use crate::musical::pitch::Pitch;
+    use crate::musical::scale::Scale;
+    use crate::sequencer::arp::ArpMode;
+    use crate::sequencer::euclid::Euclid;
+    use crate::sequencer::sequencer::{AccentMode, GateLength, GateMode, Sequencer};
     use crate::sequencer::sequencer::GateMode::Repeat;
+    use crate::sequencer::stage_mode::StageMode;
+    use crate::sequencer::step_script::StepScript;
+    use crate::sequencer::trig_condition::TrigCondition;
+    use crate::tuning::system::Tuning;
 
     #[test]
     fn test_gate_mode() {
         // Repeat
-        assert_eq!(Gate::Closed, Repeat.gate(2, 3, false, false));
-        assert_eq!(Gate::Open, Repeat.gate(2, 1, false, false));
-        assert_eq!(Gate::Open, Repeat.gate(2, 2, false, false));
+        assert_eq!(Gate::Closed, Repeat.gate(GateLength::Time(2), 0, 3, false, false));
+        assert_eq!(Gate::Open, Repeat.gate(GateLength::Time(2), 0, 1, false, false));
+        assert_eq!(Gate::Open, Repeat.gate(GateLength::Time(2), 0, 2, false, false));
+        assert_eq!(Gate::Open, Repeat.gate(GateLength::Percent(25), 8, 2, false, false));
+        assert_eq!(Gate::Closed, Repeat.gate(GateLength::Percent(25), 8, 3, false, false));
+    }
+
+    #[test]
+    fn test_gate_length() {
+        let mut seq: Sequencer<2> = Sequencer::new();
+        seq.config().set_gate_time_us(100);
+        seq.config().set_pulse_time_us(1000);
+        seq.config().stage(1).unwrap().gate_length = Some(GateLength::Percent(90));
+        assert_eq!(Gate::Closed, seq.state(500).gate);
+        seq.step();
+        assert_eq!(Gate::Open, seq.state(500).gate);
+        assert_eq!(Gate::Closed, seq.state(950).gate);
+
+        seq.config().set_pulse_time_us(2000);
+        assert_eq!(Gate::Open, seq.state(1500).gate);
+        seq.config().stage(1).unwrap().gate_length = Some(GateLength::Time(300));
+        assert_eq!(Gate::Closed, seq.state(500).gate);
+
+        seq.config().stage(1).unwrap().gate_length = Some(GateLength::Percent(80));
+        seq.config().stage(1).unwrap().ratchets = 2;
+        assert_eq!(Gate::Open, seq.state(1400).gate);
+        assert_eq!(Gate::Closed, seq.state(1600).gate);
+    }
+
+    #[test]
+    fn test_accent() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_gate_time_us(100);
+        let stage = seq.config().stage(0).unwrap();
+        stage.pulse_count = 2;
+        stage.accent = AccentMode::First;
+        assert_eq!(Gate::Open, seq.state(0).accent);
+        assert_eq!(Gate::Closed, seq.state(200).accent);
+        seq.step();
+        assert_eq!(Gate::Open, seq.state(0).gate);
+        assert_eq!(Gate::Closed, seq.state(0).accent);
+
+        seq.config().stage(0).unwrap().accent = AccentMode::All;
+        assert_eq!(Gate::Open, seq.state(0).accent);
+        seq.config().stage(0).unwrap().gate_mode = GateMode::Silent;
+        assert_eq!(Gate::Closed, seq.state(0).accent);
+    }
+
+    #[test]
+    fn test_stage_count() {
+        let mut seq = Sequencer::<16>::new();
+        assert_eq!(16, seq.config().stages().len());
+        for i in 1..16 {
+            seq.step();
+            assert_eq!(i, seq.state(0).pos.stage);
+        }
+        seq.step();
+        assert_eq!(0, seq.state(0).pos.stage);
+
+        let mut seq: Sequencer = Sequencer::new();
+        assert_eq!(8, seq.config().stages().len());
+    }
+
+    #[test]
+    fn test_ratchets() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_gate_time_us(100);
+        seq.config().set_pulse_time_us(1000);
+        seq.config().stage(0).unwrap().ratchets = 4;
+        assert_eq!(Gate::Open, seq.state(0).gate);
+        assert_eq!(Gate::Closed, seq.state(150).gate);
+        assert_eq!(Gate::Open, seq.state(250).gate);
+        assert_eq!(Gate::Open, seq.state(760).gate);
+        assert_eq!(Gate::Closed, seq.state(860).gate);
+        assert_eq!(Gate::Closed, seq.state(1200).gate);
+
+        seq.config().set_gate_time_us(400);
+        assert_eq!(Gate::Closed, seq.state(130).gate);
+    }
+
+    #[test]
+    fn test_slide() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_pulse_time_us(1000);
+        seq.config().stage(0).unwrap().note = Pitch(48);
+        let stage = seq.config().stage(1).unwrap();
+        stage.note = Pitch(52);
+        stage.pulse_count = 2;
+        stage.slide = true;
+        stage.slide_time_us = 1600;
+        assert_eq!(48.0, seq.state(500).pitch);
+
+        seq.step();
+        assert_eq!(48.0, seq.state(0).pitch);
+        assert_eq!(49.0, seq.state(400).pitch);
+        seq.step();
+        assert_eq!(51.0, seq.state(200).pitch);
+        assert_eq!(52.0, seq.state(600).pitch);
+
+        let stage = seq.config().stage(0).unwrap();
+        stage.note = Pitch(40);
+        stage.slide = true;
+        stage.slide_time_us = 1000;
+        stage.cv = 0.5;
+        stage.cv_slide = true;
+        seq.reset();
+        assert_eq!(40.0, seq.state(0).pitch);
+        assert_eq!(0.5, seq.state(0).cv);
+    }
+
+    #[test]
+    fn test_cv() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_pulse_time_us(1000);
+        seq.config().stage(0).unwrap().cv = 0.25;
+        let stage = seq.config().stage(1).unwrap();
+        stage.cv = 0.75;
+        stage.slide_time_us = 1000;
+        assert_eq!(0.25, seq.state(500).cv);
+
+        seq.step();
+        assert_eq!(0.75, seq.state(0).cv);
+        seq.config().stage(1).unwrap().cv_slide = true;
+        assert_eq!(0.25, seq.state(0).cv);
+        assert_eq!(0.5, seq.state(500).cv);
+        assert_eq!(12.0, seq.state(500).pitch);
+        assert_eq!(0.75, seq.state(1000).cv);
+    }
+
+    #[test]
+    fn test_probability() {
+        fn gates(seed: u32) -> [Gate; 32] {
+            let mut seq: Sequencer = Sequencer::new();
+            seq.config().set_rnd_seed(seed);
+            for i in 0..8 {
+                seq.config().stage(i).unwrap().probability = 50;
+            }
+            let mut gates = [Gate::Closed; 32];
+            for gate in gates.iter_mut() {
+                seq.step();
+                *gate = seq.state(0).gate;
+            }
+            gates
+        }
+
+        let gates_a = gates(1);
+        assert_eq!(gates_a, gates(1));
+        assert!(gates_a.contains(&Gate::Open));
+        assert!(gates_a.contains(&Gate::Closed));
+
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().stage(1).unwrap().probability = 0;
+        seq.config().stage(1).unwrap().gate_mode = GateMode::Sustain;
+        seq.step();
+        assert_eq!(Gate::Closed, seq.state(0).gate);
+        seq.step();
+        assert_eq!(Gate::Open, seq.state(0).gate);
+    }
+
+    #[test]
+    fn test_first_pass() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().stage(0).unwrap().probability = 0;
+        assert_eq!(Gate::Closed, seq.state(0).gate);
+
+        let stage = seq.config().stage(0).unwrap();
+        stage.probability = 100;
+        stage.condition = TrigCondition::NotFirst;
+        assert_eq!(Gate::Closed, seq.state(0).gate);
+        seq.config().stage(1).unwrap().condition = TrigCondition::NotPre;
+        seq.step();
+        assert_eq!(Gate::Open, seq.state(0).gate);
+
+        seq.reset();
+        assert_eq!(Gate::Closed, seq.state(0).gate);
+        seq.config().stage(0).unwrap().condition = TrigCondition::Always;
+        assert_eq!(Gate::Open, seq.state(0).gate);
+    }
+
+    #[test]
+    fn test_iteration() {
+        let mut seq: Sequencer<4> = Sequencer::new();
+        seq.config().set_stage_mode(StageMode::PingPong);
+        seq.reset();
+        let mut iterations = [0; 7];
+        for iteration in iterations.iter_mut() {
+            seq.step();
+            *iteration = seq.iteration();
+        }
+        assert_eq!([0, 0, 0, 0, 0, 1, 1], iterations);
+
+        seq.config().set_stage_mode(StageMode::Script(StepScript::new(&[0, 2, 0, 3])));
+        seq.reset();
+        let mut iterations = [0; 5];
+        for iteration in iterations.iter_mut() {
+            seq.step();
+            *iteration = seq.iteration();
+        }
+        assert_eq!([0, 0, 0, 1, 1], iterations);
+
+        seq.config().set_stage_mode(StageMode::Diverge);
+        seq.reset();
+        assert_eq!(1, seq.state(0).pos.stage);
+        for _ in 0..4 {
+            seq.step();
+        }
+        assert_eq!((1, 1), (seq.state(0).pos.stage, seq.iteration()));
+    }
+
+    #[test]
+    fn test_trig_condition() {
+        let mut seq: Sequencer = Sequencer::new();
+        for i in 2..8 {
+            seq.config().stage(i).unwrap().pulse_count = 0;
+        }
+        seq.config().stage(0).unwrap().condition = TrigCondition::NotFirst;
+        seq.config().stage(1).unwrap().condition = TrigCondition::Iteration(2, 3);
+
+        let mut gates = [Gate::Closed; 8];
+        for gate in gates.iter_mut() {
+            seq.step();
+            *gate = seq.state(0).gate;
+        }
+        use Gate::{Closed, Open};
+        assert_eq!([Closed, Open, Open, Open, Closed, Open, Closed, Open], gates);
+        assert_eq!(4, seq.iteration());
+
+        seq.config().stage(0).unwrap().condition = TrigCondition::Fill;
+        seq.config().stage(1).unwrap().condition = TrigCondition::NotPre;
+        seq.step();
+        assert_eq!(Closed, seq.state(0).gate);
+        seq.set_fill(true);
+        seq.step();
+        assert_eq!(Open, seq.state(0).gate);
+    }
+
+    #[test]
+    fn test_swing() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_pulse_time_us(1000);
+        seq.config().set_gate_time_us(500);
+        assert_eq!(1000, seq.next_pulse_us());
+
+        seq.config().set_swing(25);
+        assert_eq!(1250, seq.next_pulse_us());
+        assert_eq!(Gate::Open, seq.state(600).gate);
+        seq.step();
+        assert_eq!(750, seq.next_pulse_us());
+        assert_eq!(Gate::Closed, seq.state(400).gate);
+        seq.step();
+        assert_eq!(1250, seq.next_pulse_us());
+
+        seq.config().set_swing(100);
+        assert_eq!(1750, seq.next_pulse_us());
+    }
+
+    #[test]
+    fn test_loop_window() {
+        let mut seq: Sequencer = Sequencer::new();
+        seq.config().set_loop(4, 2);
+        assert_eq!((2, 4), seq.config().loop_window());
+        let mut stages = [0; 4];
+        for stage in stages.iter_mut() {
+            seq.step();
+            *stage = seq.state(0).pos.stage;
+        }
+        assert_eq!([2, 3, 4, 2], stages);
+
+        seq.config().shift_loop(5);
+        assert_eq!((5, 7), seq.config().loop_window());
+        seq.config().set_stage_mode(StageMode::PingPong);
+        for stage in stages.iter_mut() {
+            seq.step();
+            *stage = seq.state(0).pos.stage;
+        }
+        assert_eq!([5, 6, 7, 6], stages);
+        assert_eq!(0b_1110_0000, seq.config().has_pulses_mask().0);
+    }
+
+    #[test]
+    fn test_gate_mask() {
+        let mut seq: Sequencer = Sequencer::new();
+        Euclid::new(3, 8, 1).apply(seq.config());
+        let mut gates = [Gate::Closed; 8];
+        for gate in gates.iter_mut() {
+            seq.step();
+            *gate = seq.state(0).gate;
+        }
+        use Gate::{Closed, Open};
+        assert_eq!([Open, Closed, Closed, Open, Closed, Closed, Open, Closed], gates);
+    }
+
+    #[test]
+    fn test_arp() {
+        let mut seq: Sequencer<2> = Sequencer::new();
+        let stage = seq.config().stage(1).unwrap();
+        stage.note = Pitch(60);
+        stage.pulse_count = 6;
+        stage.chord = Some(Chord::new(&[7, 0, 4]));
+        stage.arp = ArpMode::UpDown;
+
+        let mut notes = [0; 6];
+        for note in notes.iter_mut() {
+            seq.step();
+            *note = seq.state(0).note.0;
+        }
+        assert_eq!([60, 64, 67, 64, 60, 64], notes);
+
+        seq.config().stage(0).unwrap().chord = Some(Chord::from(ChordType::Minor7));
+        seq.config().stage(0).unwrap().pulse_count = 4;
+        seq.config().stage(0).unwrap().arp = ArpMode::Down;
+        seq.config().stage(1).unwrap().arp = ArpMode::AsPlayed;
+        seq.config().set_key(Key::from(Scale::Major));
+        let mut notes = [0; 8];
+        for note in notes.iter_mut() {
+            seq.step();
+            *note = seq.state(0).note.0;
+        }
+        assert_eq!([21, 19, 14, 12, 67, 60, 64, 67], notes);
+    }
+
+    #[test]
+    fn test_tuning() {
+        let mut seq: Sequencer<2> = Sequencer::new();
+        seq.config().stage(0).unwrap().note = Pitch(64);
+        seq.config().set_key(Key::from(Scale::Major));
+        seq.reset();
+        assert_eq!(Pitch(64), seq.state(0).note);
+
+        assert!((seq.state(0).voltage - Pitch(64).voltage()).abs() < 1e-6);
+
+        seq.config().set_tuning(Tuning::Edo(31));
+        assert_eq!(Tuning::Edo(31).note_steps(), seq.config().note_steps());
+        assert_eq!(Pitch(65), seq.state(0).note);
+        assert!((seq.state(0).voltage - (Pitch(60).voltage() + 5.0 / 31.0)).abs() < 1e-6);
+
+        let stage = seq.config().stage(0).unwrap();
+        stage.note = Pitch(60);
+        stage.chord = Some(Chord::from(ChordType::Major));
+        stage.pulse_count = 3;
+        seq.config().set_key(Key::from(Scale::Chromatic));
+        seq.reset();
+        let mut notes = [0; 3];
+        for note in notes.iter_mut() {
+            *note = seq.state(0).note.0;
+            seq.step();
+        }
+        assert_eq!([60, 70, 78], notes);
     }
 }
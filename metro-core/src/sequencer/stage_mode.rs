@@ -1,98 +1,239 @@
 use Direction::{Forward, Reverse};
 
-use crate::sequencer::sequencer::{Direction, MaskU8, Position};
+use crate::sequencer::sequencer::{Direction, MaskU32, Position};
+use crate::sequencer::step_script::StepScript;
 
 #[derive(Debug, Clone, Copy)]
+#[repr(u8)]
 pub enum StageMode {
     Forward = 0,
     Reverse = 1,
     PingPong = 2,
     Brownian = 3,
     Random = 4,
+    /// Plays every active stage once in random order before reshuffling.
+    Shuffle = 5,
+    /// Like PingPong, but plays the first and last stage twice.
+    Pendulum = 6,
+    /// Alternates between the outer stages, working inwards.
+    Converge = 7,
+    /// Alternates outwards from the middle stages.
+    Diverge = 8,
+    /// Plays the odd-numbered stages (1, 3, 5, ...) and then the even-numbered ones.
+    OddEven = 9,
+    /// Plays stages in the script's order, leaving out stages without pulses.
+    Script(StepScript) = 10,
 }
 
+/// Stages 1, 3, 5, ... counting from one.
+const ODD_STAGES: u32 = 0x5555_5555;
+
 impl StageMode {
-    pub fn next_stage(self, stage_mask: MaskU8, pos: Position, rng: oorandom::Rand32) -> Position {
+    pub fn next_stage(self, stage_mask: MaskU32, pos: Position, rng: &mut oorandom::Rand32) -> Position {
         match self {
             Self::Forward => Self::forward(stage_mask, pos),
             Self::Reverse => Self::reverse(stage_mask, pos),
             Self::PingPong => Self::ping_pong(stage_mask, pos),
             Self::Brownian => Self::brownian(stage_mask, pos, rng),
             Self::Random => Self::random(stage_mask, pos, rng),
+            Self::Shuffle => Self::shuffle(stage_mask, pos, rng),
+            Self::Pendulum => Self::pendulum(stage_mask, pos),
+            Self::Converge => Self::converge(stage_mask, pos),
+            Self::Diverge => Self::diverge(stage_mask, pos),
+            Self::OddEven => Self::odd_even(stage_mask, pos),
+            Self::Script(script) => Self::script(script, stage_mask, pos),
+        }
+    }
+
+    /// Whether `pos` is where the mode's traversal wraps back to its start, which begins a new
+    /// iteration. `None` for the random modes, which have no fixed order.
+    pub fn starts_cycle(self, stage_mask: MaskU32, pos: Position) -> Option<bool> {
+        let start = match self {
+            Self::Forward | Self::PingPong | Self::Converge => stage_mask.lowest(),
+            Self::Reverse => stage_mask.highest(),
+            Self::Pendulum => return Some(pos.dir == Forward && Some(pos.stage) == stage_mask.lowest()),
+            Self::Diverge => stage_mask.nth(stage_mask.count().saturating_sub(1) / 2),
+            Self::OddEven => MaskU32(stage_mask.0 & ODD_STAGES).lowest().or_else(|| stage_mask.lowest()),
+            Self::Script(script) => return Some(script.first(stage_mask).map(|(step, _)| step) == Some(pos.step)),
+            Self::Brownian | Self::Random | Self::Shuffle => return None,
+        };
+        Some(start == Some(pos.stage))
+    }
+
+    fn script(script: StepScript, stage_mask: MaskU32, pos: Position) -> Position {
+        match script.next(stage_mask, pos.step) {
+            Some((step, stage)) => Position { step, ..Self::jump(pos, stage) },
+            None => Position { stage: pos.stage, pulse: 0, ..pos },
+        }
+    }
+
+    fn odd_even(stage_mask: MaskU32, pos: Position) -> Position {
+        let odd = MaskU32(stage_mask.0 & ODD_STAGES);
+        let even = MaskU32(stage_mask.0 & !ODD_STAGES);
+        let (current, other) = if MaskU32(ODD_STAGES).is_set(pos.stage) { (odd, even) } else { (even, odd) };
+        match current.next_higher(pos.stage).or_else(|| other.lowest()).or_else(|| current.lowest()) {
+            Some(p) => Self::jump(pos, p),
+            None => Position { stage: pos.stage, pulse: 0, ..pos },
+        }
+    }
+
+    /// Order for eight stages: 3, 4, 2, 5, 1, 6, 0, 7. `dir` follows each jump.
+    fn diverge(stage_mask: MaskU32, pos: Position) -> Position {
+        let count = stage_mask.count();
+        let start = match stage_mask.nth(count.saturating_sub(1) / 2) {
+            Some(p) => p,
+            None => return Position { stage: pos.stage, pulse: 0, ..pos },
+        };
+        if !stage_mask.is_set(pos.stage) {
+            return Self::jump(pos, start);
+        }
+        let rank = stage_mask.rank(pos.stage);
+        let mirror = count - 1 - rank;
+        let next = match rank {
+            r if r < mirror => mirror,
+            r if r == mirror => r.saturating_sub(1),
+            _ if mirror == 0 => return Self::jump(pos, start),
+            _ => mirror - 1,
+        };
+        Self::jump(pos, stage_mask.nth(next).expect("should exist"))
+    }
+
+    /// Order for eight stages: 0, 7, 1, 6, 2, 5, 3, 4. `dir` follows each jump.
+    fn converge(stage_mask: MaskU32, pos: Position) -> Position {
+        let count = stage_mask.count();
+        let start = match stage_mask.lowest() {
+            Some(p) => p,
+            None => return Position { stage: pos.stage, pulse: 0, ..pos },
+        };
+        if !stage_mask.is_set(pos.stage) {
+            return Self::jump(pos, start);
+        }
+        let rank = stage_mask.rank(pos.stage);
+        let mirror = count - 1 - rank;
+        match rank {
+            r if r < mirror => Self::jump(pos, stage_mask.nth(mirror).expect("should exist")),
+            r if mirror + 1 < r => Self::jump(pos, stage_mask.nth(mirror + 1).expect("should exist")),
+            _ => Self::jump(pos, start),
+        }
+    }
+
+    fn jump(pos: Position, stage: u8) -> Position {
+        let dir = match stage {
+            s if s > pos.stage => Forward,
+            s if s < pos.stage => Reverse,
+            _ => pos.dir,
+        };
+        Position { stage, pulse: 0, dir, ..pos }
+    }
+
+    fn pendulum(stage_mask: MaskU32, pos: Position) -> Position {
+        let lower = stage_mask.next_lower(pos.stage);
+        let higher = stage_mask.next_higher(pos.stage);
+        match (pos.dir, lower, higher) {
+            (Forward, _, Some(p)) =>
+                Position { stage: p, pulse: 0, dir: Forward, ..pos },
+            (Reverse, Some(p), _) =>
+                Position { stage: p, pulse: 0, dir: Reverse, ..pos },
+            (Forward, _, None) =>
+                Position { stage: if stage_mask.is_set(pos.stage) { pos.stage } else { lower.unwrap_or(pos.stage) }, pulse: 0, dir: Reverse, ..pos },
+            (Reverse, None, _) =>
+                Position { stage: if stage_mask.is_set(pos.stage) { pos.stage } else { higher.unwrap_or(pos.stage) }, pulse: 0, dir: Forward, ..pos },
+        }
+    }
+
+    fn shuffle(stage_mask: MaskU32, pos: Position, rng: &mut oorandom::Rand32) -> Position {
+        let current = 1 << pos.stage;
+        let mut played = (pos.played.0 | current) & stage_mask.0;
+        if stage_mask.0 & !played == 0 {
+            played = current & stage_mask.0;
+        }
+        let remaining = MaskU32(stage_mask.0 & !played);
+        match remaining.nth(rng.rand_range(0..remaining.count().max(1) as u32) as u8) {
+            Some(p) => Position { played: MaskU32(played | 1 << p), ..Self::jump(pos, p) },
+            None => Position { stage: pos.stage, pulse: 0, ..pos },
         }
     }
 
-    fn random(stage_mask: MaskU8, pos: Position, mut rng: oorandom::Rand32) -> Position {
-        let idx = rng.rand_range(0..8) as u8;
+    fn random(stage_mask: MaskU32, pos: Position, rng: &mut oorandom::Rand32) -> Position {
+        let (lowest, highest) = match (stage_mask.lowest(), stage_mask.highest()) {
+            (Some(l), Some(h)) => (l, h),
+            _ => return Position { stage: pos.stage, pulse: 0, dir: pos.dir, ..pos },
+        };
+        let idx = rng.rand_range(lowest as u32..highest as u32 + 1) as u8;
+        if stage_mask.is_set(idx) {
+            return Position { stage: idx, pulse: 0, dir: pos.dir, ..pos };
+        }
         let lower = stage_mask.next_lower(idx);
         let higher = stage_mask.next_higher(idx);
         match (lower, higher) {
             (Some(i), None) | (None, Some(i)) =>
-                Position { stage: i, pulse: 0, dir: pos.dir },
+                Position { stage: i, pulse: 0, dir: pos.dir, ..pos },
             (Some(l), Some(h)) => {
                 if idx - l < h - idx {
-                    Position { stage: l, pulse: 0, dir: pos.dir }
+                    Position { stage: l, pulse: 0, dir: pos.dir, ..pos }
                 } else {
-                    Position { stage: h, pulse: 0, dir: pos.dir }
+                    Position { stage: h, pulse: 0, dir: pos.dir, ..pos }
                 }
             }
             (None, None) =>
-                Position { stage: pos.stage, pulse: 0, dir: pos.dir },
+                Position { stage: pos.stage, pulse: 0, dir: pos.dir, ..pos },
         }
     }
 
-    fn brownian(stage_mask: MaskU8, pos: Position, mut rng: oorandom::Rand32) -> Position {
+    fn brownian(stage_mask: MaskU32, pos: Position, rng: &mut oorandom::Rand32) -> Position {
         match (rng.rand_float(), rng.rand_float()) {
             (a, _) if a > 0.5 => Self::forward(stage_mask, pos),
-            (_, b) if b > 0.5 => Position { stage: pos.stage, pulse: 0, dir: pos.dir },
+            (_, b) if b > 0.5 => Position { stage: pos.stage, pulse: 0, dir: pos.dir, ..pos },
             _ => Self::reverse(stage_mask, pos),
         }
     }
 
-    fn ping_pong(stage_mask: MaskU8, pos: Position) -> Position {
+    fn ping_pong(stage_mask: MaskU32, pos: Position) -> Position {
         let lower = stage_mask.next_lower(pos.stage);
         let higher = stage_mask.next_higher(pos.stage);
         let dir = pos.dir;
         match (dir, lower, higher) {
             (Forward, Some(p), None) =>
-                Position { stage: p, pulse: 0, dir: Reverse },
+                Position { stage: p, pulse: 0, dir: Reverse, ..pos },
             (Reverse, None, Some(p)) =>
-                Position { stage: p, pulse: 0, dir: Forward },
+                Position { stage: p, pulse: 0, dir: Forward, ..pos },
             (Forward, _, Some(p)) =>
-                Position { stage: p, pulse: 0, dir: Forward },
+                Position { stage: p, pulse: 0, dir: Forward, ..pos },
             (Reverse, Some(p), _) =>
-                Position { stage: p, pulse: 0, dir: Reverse },
+                Position { stage: p, pulse: 0, dir: Reverse, ..pos },
             _ =>
-                Position { stage: pos.stage, pulse: 0, dir: pos.dir }
+                Position { stage: pos.stage, pulse: 0, dir: pos.dir, ..pos }
         }
     }
 
-    fn reverse(stage_mask: MaskU8, pos: Position) -> Position {
+    fn reverse(stage_mask: MaskU32, pos: Position) -> Position {
         let lower = stage_mask.next_lower(pos.stage);
         let highest = stage_mask.highest().expect("should exist");
         match lower {
-            Some(p) => Position { stage: p, pulse: 0, dir: Reverse },
-            None => Position { stage: highest, pulse: 0, dir: Reverse },
+            Some(p) => Position { stage: p, pulse: 0, dir: Reverse, ..pos },
+            None => Position { stage: highest, pulse: 0, dir: Reverse, ..pos },
         }
     }
 
-    fn forward(stage_mask: MaskU8, pos: Position) -> Position {
+    fn forward(stage_mask: MaskU32, pos: Position) -> Position {
         let higher = stage_mask.next_higher(pos.stage);
         let lowest = stage_mask.lowest().expect("should exist");
         match higher {
-            Some(p) => Position { stage: p, pulse: 0, dir: Forward },
-            None => Position { stage: lowest, pulse: 0, dir: Forward },
+            Some(p) => Position { stage: p, pulse: 0, dir: Forward, ..pos },
+            None => Position { stage: lowest, pulse: 0, dir: Forward, ..pos },
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::sequencer::sequencer::{Direction, MaskU8, Position};
-    use crate::sequencer::stage_mode::StageMode::{Forward, PingPong, Reverse};
+    use crate::sequencer::sequencer::{Direction, MaskU32, Position};
+    use crate::sequencer::stage_mode::StageMode;
+    use crate::sequencer::stage_mode::StageMode::{Converge, Diverge, Forward, OddEven, Pendulum, PingPong, Random, Reverse, Script, Shuffle};
+    use crate::sequencer::step_script::StepScript;
 
     fn pos(stage: u8, dir: Direction) -> Position {
-        Position { stage, pulse: 0, dir }
+        Position { stage, pulse: 0, dir, played: MaskU32::new(), step: 0 }
     }
 
     fn pos_fwd(stage: u8) -> Position {
@@ -103,34 +244,101 @@ mod tests {
         pos(stage, Direction::Reverse)
     }
 
+    fn rng() -> oorandom::Rand32 {
+        oorandom::Rand32::new(0)
+    }
+
     #[test]
     fn test_next_stage() {
         // Forward
-        assert_eq!(0, Forward.next_stage(MaskU8(0b_0000_0001), pos_fwd(0)).stage);
-        assert_eq!(1, Forward.next_stage(MaskU8(0b_0000_0011), pos_fwd(0)).stage);
-        assert_eq!(1, Forward.next_stage(MaskU8(0b_0000_0010), pos_fwd(0)).stage);
-        assert_eq!(0, Forward.next_stage(MaskU8(0b_0000_0001), pos_fwd(7)).stage);
-        assert_eq!(1, Forward.next_stage(MaskU8(0b_0000_0010), pos_fwd(7)).stage);
+        assert_eq!(0, Forward.next_stage(MaskU32(0b_0000_0001), pos_fwd(0), &mut rng()).stage);
+        assert_eq!(1, Forward.next_stage(MaskU32(0b_0000_0011), pos_fwd(0), &mut rng()).stage);
+        assert_eq!(1, Forward.next_stage(MaskU32(0b_0000_0010), pos_fwd(0), &mut rng()).stage);
+        assert_eq!(0, Forward.next_stage(MaskU32(0b_0000_0001), pos_fwd(7), &mut rng()).stage);
+        assert_eq!(1, Forward.next_stage(MaskU32(0b_0000_0010), pos_fwd(7), &mut rng()).stage);
 
         // Reverse
-        assert_eq!(7, Reverse.next_stage(MaskU8(0b_1000_0000), pos_rev(7)).stage);
-        assert_eq!(6, Reverse.next_stage(MaskU8(0b_1100_0000), pos_rev(7)).stage);
-        assert_eq!(6, Reverse.next_stage(MaskU8(0b_0100_0000), pos_rev(7)).stage);
-        assert_eq!(7, Reverse.next_stage(MaskU8(0b_1000_0000), pos_rev(0)).stage);
-        assert_eq!(6, Reverse.next_stage(MaskU8(0b_0100_0000), pos_rev(0)).stage);
+        assert_eq!(7, Reverse.next_stage(MaskU32(0b_1000_0000), pos_rev(7), &mut rng()).stage);
+        assert_eq!(6, Reverse.next_stage(MaskU32(0b_1100_0000), pos_rev(7), &mut rng()).stage);
+        assert_eq!(6, Reverse.next_stage(MaskU32(0b_0100_0000), pos_rev(7), &mut rng()).stage);
+        assert_eq!(7, Reverse.next_stage(MaskU32(0b_1000_0000), pos_rev(0), &mut rng()).stage);
+        assert_eq!(6, Reverse.next_stage(MaskU32(0b_0100_0000), pos_rev(0), &mut rng()).stage);
 
         // PingPong Forward
-        assert_eq!(0, PingPong.next_stage(MaskU8(0b_0000_0001), pos_fwd(0)).stage);
-        assert_eq!(1, PingPong.next_stage(MaskU8(0b_0000_0011), pos_fwd(0)).stage);
-        assert_eq!(0, PingPong.next_stage(MaskU8(0b_0100_0001), pos_fwd(6)).stage);
-        assert_eq!(5, PingPong.next_stage(MaskU8(0b_0110_0001), pos_fwd(6)).stage);
-        assert_eq!(Direction::Reverse, PingPong.next_stage(MaskU8(0b_0110_0001), pos_fwd(6)).dir);
+        assert_eq!(0, PingPong.next_stage(MaskU32(0b_0000_0001), pos_fwd(0), &mut rng()).stage);
+        assert_eq!(1, PingPong.next_stage(MaskU32(0b_0000_0011), pos_fwd(0), &mut rng()).stage);
+        assert_eq!(0, PingPong.next_stage(MaskU32(0b_0100_0001), pos_fwd(6), &mut rng()).stage);
+        assert_eq!(5, PingPong.next_stage(MaskU32(0b_0110_0001), pos_fwd(6), &mut rng()).stage);
+        assert_eq!(Direction::Reverse, PingPong.next_stage(MaskU32(0b_0110_0001), pos_fwd(6), &mut rng()).dir);
 
         // PingPong Reverse
-        assert_eq!(7, PingPong.next_stage(MaskU8(0b_1000_0000), pos_rev(7)).stage);
-        assert_eq!(6, PingPong.next_stage(MaskU8(0b_1100_0000), pos_rev(7)).stage);
-        assert_eq!(7, PingPong.next_stage(MaskU8(0b_1000_0010), pos_rev(1)).stage);
-        assert_eq!(2, PingPong.next_stage(MaskU8(0b_1000_0110), pos_rev(1)).stage);
-        assert_eq!(Direction::Forward, PingPong.next_stage(MaskU8(0b_1000_0110), pos_rev(1)).dir);
+        assert_eq!(7, PingPong.next_stage(MaskU32(0b_1000_0000), pos_rev(7), &mut rng()).stage);
+        assert_eq!(6, PingPong.next_stage(MaskU32(0b_1100_0000), pos_rev(7), &mut rng()).stage);
+        assert_eq!(7, PingPong.next_stage(MaskU32(0b_1000_0010), pos_rev(1), &mut rng()).stage);
+        assert_eq!(2, PingPong.next_stage(MaskU32(0b_1000_0110), pos_rev(1), &mut rng()).stage);
+        assert_eq!(Direction::Forward, PingPong.next_stage(MaskU32(0b_1000_0110), pos_rev(1), &mut rng()).dir);
+    }
+
+    #[test]
+    fn test_next_stage_wide() {
+        let wide = MaskU32(1 << 31 | 1 << 16 | 1);
+        assert_eq!(16, Forward.next_stage(wide, pos_fwd(0), &mut rng()).stage);
+        assert_eq!(31, Forward.next_stage(wide, pos_fwd(16), &mut rng()).stage);
+        assert_eq!(0, Forward.next_stage(wide, pos_fwd(31), &mut rng()).stage);
+        assert_eq!(31, Reverse.next_stage(wide, pos_rev(0), &mut rng()).stage);
+        assert_eq!(16, PingPong.next_stage(wide, pos_rev(31), &mut rng()).stage);
+        assert_eq!(Direction::Reverse, PingPong.next_stage(wide, pos_fwd(31), &mut rng()).dir);
+
+        let mut rng = rng();
+        for _ in 0..64 {
+            let stage = Random.next_stage(wide, pos_fwd(0), &mut rng).stage;
+            assert!(wide.is_set(stage));
+        }
+    }
+
+    fn walk(mode: StageMode, stage_mask: MaskU32, start: Position) -> [u8; 10] {
+        let mut rng = rng();
+        let mut pos = start;
+        let mut stages = [0; 10];
+        for stage in stages.iter_mut() {
+            pos = mode.next_stage(stage_mask, pos, &mut rng);
+            *stage = pos.stage;
+        }
+        stages
+    }
+
+    #[test]
+    fn test_next_stage_extended() {
+        let all = MaskU32(0b_1111_1111);
+        assert_eq!([1, 2, 3, 4, 5, 6, 7, 7, 6, 5], walk(Pendulum, all, pos_fwd(0)));
+        assert_eq!([2, 2, 1, 1, 2, 2, 1, 1, 2, 2], walk(Pendulum, MaskU32(0b_0000_0110), pos_fwd(1)));
+        assert_eq!([7, 1, 6, 2, 5, 3, 4, 0, 7, 1], walk(Converge, all, pos_fwd(0)));
+        assert_eq!([4, 2, 5, 1, 6, 0, 7, 3, 4, 2], walk(Diverge, all, pos_fwd(3)));
+        assert_eq!([2, 1, 3, 0, 4, 2, 1, 3, 0, 4], walk(Diverge, MaskU32(0b_0001_1111), pos_fwd(7)));
+        assert_eq!([2, 4, 6, 1, 3, 5, 7, 0, 2, 4], walk(OddEven, all, pos_fwd(0)));
+        assert_eq!([6, 1, 5, 6, 1, 5, 6, 1, 5, 6], walk(OddEven, MaskU32(0b_0110_0010), pos_fwd(0)));
+        assert_eq!(Direction::Forward, Converge.next_stage(all, pos_rev(0), &mut rng()).dir);
+        assert_eq!(Direction::Reverse, Converge.next_stage(all, pos_fwd(7), &mut rng()).dir);
+        assert_eq!(Direction::Reverse, OddEven.next_stage(all, pos_fwd(6), &mut rng()).dir);
+        assert_eq!(Direction::Forward, OddEven.next_stage(all, pos_rev(1), &mut rng()).dir);
+        assert_eq!(Direction::Reverse, Shuffle.next_stage(all, pos_fwd(7), &mut rng()).dir);
+        assert_eq!(Direction::Forward, Shuffle.next_stage(all, pos_rev(0), &mut rng()).dir);
+
+        let shuffled = walk(Shuffle, all, pos_fwd(0));
+        let mut seen = MaskU32(1);
+        for stage in &shuffled[..7] {
+            assert!(!seen.is_set(*stage));
+            seen = MaskU32(seen.0 | 1 << stage);
+        }
+        assert_eq!(all.0, seen.0);
+    }
+
+    #[test]
+    fn test_next_stage_script() {
+        let script = Script(StepScript::new(&[0, 0, 3, 1, 7, 2]));
+        assert_eq!([0, 3, 1, 7, 2, 0, 0, 3, 1, 7], walk(script, MaskU32(0b_1111_1111), pos_fwd(0)));
+        assert_eq!([0, 1, 2, 0, 0, 1, 2, 0, 0, 1], walk(script, MaskU32(0b_0000_0111), pos_fwd(0)));
+        assert_eq!([5, 5, 5, 5, 5, 5, 5, 5, 5, 5], walk(script, MaskU32(0b_0011_0000), pos_fwd(5)));
+        assert_eq!(Direction::Reverse, script.next_stage(MaskU32(0b_1111_1111), pos_fwd(3), &mut rng()).dir);
     }
 }
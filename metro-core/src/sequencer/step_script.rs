@@ -0,0 +1,51 @@
+use crate::sequencer::sequencer::MaskU32;
+
+/// A fixed-capacity list of stage indices played in order by `StageMode::Script`.
+/// Stages may repeat and appear in any order.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StepScript {
+    steps: [u8; StepScript::CAPACITY],
+    len: u8,
+}
+
+impl StepScript {
+    pub const CAPACITY: usize = 32;
+
+    /// Steps beyond `CAPACITY` are dropped.
+    pub fn new(steps: &[u8]) -> Self {
+        let mut script = Self { steps: [0; Self::CAPACITY], len: 0 };
+        for &stage in steps.iter().take(Self::CAPACITY) {
+            script.push(stage);
+        }
+        script
+    }
+
+    pub fn steps(&self) -> &[u8] {
+        &self.steps[..self.len as usize]
+    }
+
+    /// Returns false when the script is full.
+    pub fn push(&mut self, stage: u8) -> bool {
+        if self.len as usize >= Self::CAPACITY {
+            return false;
+        }
+        self.steps[self.len as usize] = stage;
+        self.len += 1;
+        true
+    }
+
+    /// Finds the first step after `step` whose stage is in `stage_mask`, wrapping around, and
+    /// returns its index and stage.
+    pub fn next(&self, stage_mask: MaskU32, step: u8) -> Option<(u8, u8)> {
+        let len = self.len as usize;
+        (1..=len)
+            .map(|offset| (step as usize + offset) % len)
+            .find(|&i| stage_mask.is_set(self.steps[i]))
+            .map(|i| (i as u8, self.steps[i]))
+    }
+
+    /// The first step whose stage is in `stage_mask`.
+    pub fn first(&self, stage_mask: MaskU32) -> Option<(u8, u8)> {
+        self.next(stage_mask, self.len.saturating_sub(1))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::sequencer::clock::Clocked;
+use crate::sequencer::sequencer::{DEFAULT_STAGES, Sequencer, State};
+
+/// Runs `T` independent sequencer tracks from one clock. Tracks with different lengths drift
+/// against each other, which gives polymeter.
+#[derive(Debug, Clone)]
+pub struct Tracks<const T: usize, const N: usize = DEFAULT_STAGES> {
+    tracks: [Sequencer<N>; T],
+}
+
+impl<const T: usize, const N: usize> Tracks<T, N> {
+    pub fn new() -> Self {
+        Self { tracks: core::array::from_fn(|_| Sequencer::new()) }
+    }
+
+    pub fn track(&mut self, index: usize) -> Option<&mut Sequencer<N>> {
+        self.tracks.get_mut(index)
+    }
+
+    pub fn state(&self, last_beat_us: u32) -> [State; T] {
+        core::array::from_fn(|i| self.tracks[i].state(last_beat_us))
+    }
+
+    pub fn step(&mut self) {
+        self.tracks.iter_mut().for_each(|t| t.step())
+    }
+
+    pub fn reset(&mut self) {
+        self.tracks.iter_mut().for_each(|t| t.reset())
+    }
+}
+
+impl<const T: usize, const N: usize> Default for Tracks<T, N> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<const T: usize, const N: usize> Clocked for Tracks<T, N> {
+    fn step(&mut self) { Tracks::step(self) }
+
+    fn reset(&mut self) { Tracks::reset(self) }
+
+    fn set_pulse_time_us(&mut self, pulse_time_us: u32) {
+        self.tracks.iter_mut().for_each(|t| t.config().set_pulse_time_us(pulse_time_us))
+    }
+
+    fn set_gate_time_us(&mut self, gate_time_us: u32) {
+        self.tracks.iter_mut().for_each(|t| t.config().set_gate_time_us(gate_time_us))
+    }
+
+    fn set_external_clock(&mut self, external_clock: bool) {
+        self.tracks.iter_mut().for_each(|t| t.config().set_external_clock(external_clock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::pitch::Pitch;
+    use crate::musical::key::Key;
+    use crate::musical::scale::Scale;
+    use crate::sequencer::stage_mode::StageMode;
+    use crate::sequencer::tracks::Tracks;
+
+    #[test]
+    fn test_polymeter() {
+        let mut tracks: Tracks<2> = Tracks::new();
+        tracks.track(0).unwrap().config().set_length(3);
+        tracks.track(1).unwrap().config().set_length(4);
+        tracks.track(1).unwrap().config().set_stage_mode(StageMode::Reverse);
+        tracks.track(1).unwrap().config().set_key(Key::from(Scale::Major));
+        tracks.track(1).unwrap().config().stage(3).unwrap().note = Pitch(61);
+
+        tracks.step();
+        let [a, b] = tracks.state(0);
+        assert_eq!((1, 3), (a.pos.stage, b.pos.stage));
+        assert_eq!(Pitch(60), b.note);
+
+        for _ in 0..11 {
+            tracks.step();
+        }
+        let [a, b] = tracks.state(0);
+        assert_eq!((0, 0), (a.pos.stage, b.pos.stage));
+    }
+}
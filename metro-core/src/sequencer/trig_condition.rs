@@ -0,0 +1,32 @@
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TrigCondition {
+    Always,
+    /// Fires on iteration `A` of every `B` loops, counting from 1.
+    Iteration(u8, u8),
+    First,
+    NotFirst,
+    Fill,
+    NotFill,
+    Pre,
+    NotPre,
+}
+
+impl TrigCondition {
+    pub fn evaluate(self, iteration: u32, fill: bool, pre: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Iteration(a, b) => b > 0 && iteration % b as u32 == (a.max(1) as u32 - 1) % b as u32,
+            Self::First => iteration == 0,
+            Self::NotFirst => iteration > 0,
+            Self::Fill => fill,
+            Self::NotFill => !fill,
+            Self::Pre => pre,
+            Self::NotPre => !pre,
+        }
+    }
+
+    /// Whether the result of this condition is what `Pre` and `NotPre` look back at.
+    pub fn sets_pre(self) -> bool {
+        !matches!(self, Self::Always | Self::Pre | Self::NotPre)
+    }
+}
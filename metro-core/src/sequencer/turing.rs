@@ -0,0 +1,139 @@
+use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::sequencer::sequencer::{Config, Sequencer};
+
+/// A looping shift register that writes the notes of a config, after the Turing Machine module.
+/// On every loop the register rotates by one bit, and the bit coming round flips unless it is
+/// locked, so `lock` at 100 freezes the melody and lower values let it drift.
+#[derive(Debug, Clone, Copy)]
+pub struct TuringMachine {
+    register: u16,
+    length: u8,
+    lock: u8,
+    base: Pitch,
+    octaves: u8,
+}
+
+impl TuringMachine {
+    pub const MAX_LENGTH: u8 = 16;
+    pub const MAX_LOCK: u8 = 100;
+
+    /// Fills the register from `seed`, so the same seed always starts the same melody.
+    pub fn new(seed: u32) -> Self {
+        let register = oorandom::Rand32::new(seed as u64).rand_u32() as u16;
+        Self { register, length: Self::MAX_LENGTH, lock: Self::MAX_LOCK, base: Pitch::from(Note::C), octaves: 2 }
+    }
+
+    pub fn register(&self) -> u16 { self.register }
+
+    /// Recalls a register saved with `register`.
+    pub fn set_register(&mut self, register: u16) {
+        self.register = register
+    }
+
+    /// Number of bits in the loop, from 2 to 16.
+    pub fn set_length(&mut self, length: u8) {
+        self.length = length.clamp(2, Self::MAX_LENGTH)
+    }
+
+    /// Chance in percent that a bit is kept as it comes round.
+    pub fn set_lock(&mut self, lock: u8) {
+        self.lock = lock.min(Self::MAX_LOCK)
+    }
+
+    /// Notes are spread over `octaves` octaves above `base`.
+    pub fn set_range(&mut self, base: Pitch, octaves: u8) {
+        self.base = base;
+        self.octaves = octaves.max(1);
+    }
+
+    /// Rotates the loop by one bit, flipping the bit that comes round unless it is locked.
+    pub fn shift(&mut self, rng: &mut oorandom::Rand32) {
+        let mut bit = (self.register >> (self.length - 1)) & 1;
+        if self.lock < Self::MAX_LOCK && rng.rand_range(0..Self::MAX_LOCK as u32) >= self.lock as u32 {
+            bit ^= 1;
+        }
+        self.register = ((self.register << 1) | bit) & self.loop_mask();
+    }
+
+    /// The lowest eight bits of the register, or all of a shorter loop, `offset` bits further
+    /// along the loop, as 0..1.
+    pub fn value(&self, offset: u8) -> f32 {
+        let offset = offset % self.length;
+        let register = self.register & self.loop_mask();
+        let rotated = if offset == 0 { register } else { (register << offset) | (register >> (self.length - offset)) };
+        let max = (1_u16 << self.length.min(8)) - 1;
+        (rotated & max) as f32 / max as f32
+    }
+
+    /// Writes a note to every stage, each one reading the register one bit further along,
+    /// quantized to the config's key.
+    pub fn apply<const N: usize>(&self, config: &mut Config<N>) {
+        let (key, steps) = (config.key(), config.note_steps());
+        for i in 0..N {
+            let note = key.quantize_float_in(self.value(i as u8), self.base, self.octaves, steps);
+            config.stage(i).expect("stage should exist").note = note;
+        }
+    }
+
+    /// Steps the sequencer and shifts the register whenever it completes a loop.
+    pub fn step<const N: usize>(&mut self, seq: &mut Sequencer<N>) {
+        let iteration = seq.iteration();
+        seq.step();
+        if seq.iteration() != iteration {
+            self.shift(seq.config().rng());
+            self.apply(seq.config());
+        }
+    }
+
+    fn loop_mask(&self) -> u16 {
+        (u32::MAX >> (32 - self.length)) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::pitch::Pitch;
+    use crate::sequencer::sequencer::{Config, Sequencer};
+    use crate::sequencer::turing::TuringMachine;
+
+    #[test]
+    fn test_turing_machine() {
+        let mut rng = oorandom::Rand32::new(0);
+        let mut turing = TuringMachine::new(7);
+        assert_eq!(TuringMachine::new(7).register(), turing.register());
+        turing.set_length(8);
+        turing.set_register(0b_1000_0001);
+        turing.shift(&mut rng);
+        assert_eq!(0b_0000_0011, turing.register());
+        assert_eq!(0b_0000_1100 as f32 / 255.0, turing.value(2));
+
+        turing.set_lock(0);
+        turing.shift(&mut rng);
+        assert_eq!(0b_0000_0111, turing.register());
+
+        let mut config: Config<4> = Config::new();
+        turing.set_length(4);
+        turing.set_range(Pitch(48), 2);
+        turing.set_register(0b_1000);
+        assert_eq!(1.0 / 15.0, turing.value(1));
+        assert_eq!(0b_0100 as f32 / 15.0, turing.value(3));
+        turing.set_register(0);
+        turing.apply(&mut config);
+        assert_eq!(Pitch(48), config.stages()[0].note);
+        turing.set_register(0b_1111);
+        turing.apply(&mut config);
+        assert_eq!(Pitch(71), config.stages()[0].note);
+        turing.set_length(8);
+
+        let mut seq: Sequencer<4> = Sequencer::new();
+        turing.set_lock(100);
+        turing.set_range(Pitch(48), 1);
+        turing.set_register(0xFF);
+        for _ in 0..4 {
+            turing.step(&mut seq);
+        }
+        assert_eq!(0b_0000_1111, turing.register() >> 4);
+        assert_eq!(Pitch(59), seq.config().stages()[0].note);
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(any(feature = "std", test))]
+pub mod scala;
+pub mod system;
+pub mod table;
@@ -0,0 +1,254 @@
+//! Parsing of Scala scale (.scl) and keyboard mapping (.kbm) files, as described at
+//! <https://www.huygens-fokker.org/scala/scl_format.html>. The result is resolved into a
+//! `TuningTable`, which is all the firmware needs at run time.
+use std::fmt;
+use std::str::Lines;
+
+use crate::analog::semitone_voltage;
+use crate::musical::pitch::Pitch;
+use crate::tuning::system::Tuning;
+use crate::tuning::table::TuningTable;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalaError {
+    /// The file ended before the named field.
+    Missing(&'static str),
+    /// The named field could not be read from the given text.
+    Invalid(&'static str, String),
+    /// The keyboard mapping's reference note has no scale degree.
+    UnmappedReference,
+}
+
+impl fmt::Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalaError::Missing(field) => write!(f, "missing {}", field),
+            ScalaError::Invalid(field, text) => write!(f, "invalid {}: {:?}", field, text),
+            ScalaError::UnmappedReference => write!(f, "reference note is not mapped to a scale degree"),
+        }
+    }
+}
+
+impl std::error::Error for ScalaError {}
+
+/// A scale from a .scl file. `cents` holds every degree above the root, and its last entry is
+/// the period the scale repeats at, usually an octave.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalaScale {
+    pub description: String,
+    pub cents: Vec<f64>,
+}
+
+impl ScalaScale {
+    pub fn parse(text: &str) -> Result<ScalaScale, ScalaError> {
+        let mut lines = Fields(text.lines());
+        let description = lines.next_raw().ok_or(ScalaError::Missing("description"))?.trim().to_string();
+        let count: usize = lines.parse("note count")?;
+        let cents = (0..count).map(|_| lines.next("pitch").and_then(parse_pitch)).collect::<Result<Vec<_>, _>>()?;
+        if cents.is_empty() {
+            return Err(ScalaError::Invalid("note count", count.to_string()));
+        }
+        Ok(ScalaScale { description, cents })
+    }
+
+    /// Cents above the root of `degree`, which may lie in any period.
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.cents.len() as i32;
+        let period = self.cents[self.cents.len() - 1];
+        let step = match degree.rem_euclid(len) {
+            0 => 0.0,
+            i => self.cents[i as usize - 1],
+        };
+        degree.div_euclid(len) as f64 * period + step
+    }
+}
+
+/// How keys map onto scale degrees, from a .kbm file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    pub first: u8,
+    pub last: u8,
+    /// Key that plays the scale's root.
+    pub middle: u8,
+    /// Key whose frequency is given.
+    pub reference: u8,
+    pub frequency: f64,
+    /// Degree at which the mapping repeats. Unused when `map` is empty.
+    pub octave_degree: i32,
+    /// Degree for each key in one repeat of the mapping, or `None` for keys left unmapped.
+    /// Empty for a linear mapping where every key plays the next degree.
+    pub map: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    /// Maps can't repeat over more keys than a `TuningTable` holds. Entries missing from the end
+    /// of the file are left unmapped.
+    pub fn parse(text: &str) -> Result<KeyboardMapping, ScalaError> {
+        let mut lines = Fields(text.lines());
+        let size: usize = lines.parse("map size")?;
+        if size > TuningTable::LEN {
+            return Err(ScalaError::Invalid("map size", size.to_string()));
+        }
+        let first = lines.parse("first note")?;
+        let last = lines.parse("last note")?;
+        let middle = lines.parse("middle note")?;
+        let reference = lines.parse("reference note")?;
+        let frequency = lines.parse("reference frequency")?;
+        let octave_degree = lines.parse("octave degree")?;
+        let mut map = Vec::with_capacity(size);
+        while map.len() < size {
+            match lines.next("mapping") {
+                Ok("x") | Ok("X") => map.push(None),
+                Ok(field) => map.push(Some(field.parse().map_err(|_| ScalaError::Invalid("mapping", field.to_string()))?)),
+                Err(_) => map.push(None),
+            }
+        }
+        Ok(KeyboardMapping { first, last, middle, reference, frequency, octave_degree, map })
+    }
+
+    /// Scale degree played by `key`, or `None` if it is unmapped.
+    pub fn degree(&self, key: u8, scale_len: usize) -> Option<i32> {
+        if key < self.first || key > self.last {
+            return None;
+        }
+        let offset = key as i32 - self.middle as i32;
+        if self.map.is_empty() {
+            return Some(offset);
+        }
+        let size = self.map.len() as i32;
+        let octave_degree = if self.octave_degree > 0 { self.octave_degree } else { scale_len as i32 };
+        self.map[offset.rem_euclid(size) as usize].map(|d| offset.div_euclid(size) * octave_degree + d)
+    }
+
+    /// Resolves `scale` onto the keyboard. Keys outside the mapping, or left unmapped, keep
+    /// their 12-TET voltage.
+    pub fn resolve(&self, scale: &ScalaScale) -> Result<TuningTable, ScalaError> {
+        let reference_cents = self.degree(self.reference, scale.cents.len())
+            .map(|d| scale.degree_cents(d))
+            .ok_or(ScalaError::UnmappedReference)?;
+        let reference_voltage = semitone_voltage(A4.0 as f32) as f64 + (self.frequency / A4_FREQUENCY).log2();
+        let mut table = TuningTable::equal_temperament();
+        for key in 0..TuningTable::LEN as u8 {
+            if let Some(degree) = self.degree(key, scale.cents.len()) {
+                let voltage = reference_voltage + (scale.degree_cents(degree) - reference_cents) / 1200.0;
+                table.set_voltage(Pitch(key), voltage as f32);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Like `resolve`, as a tuning for `Config::set_tuning`. A period spans as many keys as the
+    /// mapping, or as the scale has degrees for a linear mapping.
+    pub fn tuning(&self, scale: &ScalaScale) -> Result<Tuning, ScalaError> {
+        let steps = if self.map.is_empty() { scale.cents.len() } else { self.map.len() };
+        Ok(Tuning::Table(self.resolve(scale)?, steps.min(u8::MAX as usize) as u8))
+    }
+}
+
+impl Default for KeyboardMapping {
+    /// A linear mapping with the root on middle C and A4 at 440 Hz.
+    fn default() -> Self {
+        KeyboardMapping { first: 0, last: Pitch::MAX.0, middle: 60, reference: A4.0, frequency: A4_FREQUENCY, octave_degree: 0, map: Vec::new() }
+    }
+}
+
+const A4: Pitch = Pitch(69);
+const A4_FREQUENCY: f64 = 440.0;
+
+/// Non-comment lines of a Scala file.
+struct Fields<'a>(Lines<'a>);
+
+impl<'a> Fields<'a> {
+    fn next_raw(&mut self) -> Option<&'a str> {
+        self.0.by_ref().find(|line| !line.starts_with('!'))
+    }
+
+    /// The first word of the next non-blank line.
+    fn next(&mut self, field: &'static str) -> Result<&'a str, ScalaError> {
+        loop {
+            let line = self.next_raw().ok_or(ScalaError::Missing(field))?;
+            if let Some(word) = line.split_whitespace().next() {
+                return Ok(word);
+            }
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&mut self, field: &'static str) -> Result<T, ScalaError> {
+        let word = self.next(field)?;
+        word.parse().map_err(|_| ScalaError::Invalid(field, word.to_string()))
+    }
+}
+
+/// Reads a pitch as cents when it has a decimal point, otherwise as a ratio like `3/2` or `2`.
+fn parse_pitch(word: &str) -> Result<f64, ScalaError> {
+    let invalid = || ScalaError::Invalid("pitch", word.to_string());
+    if word.contains('.') {
+        return word.parse().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = match word.split_once('/') {
+        Some((n, d)) => (n.parse::<u64>().map_err(|_| invalid())?, d.parse::<u64>().map_err(|_| invalid())?),
+        None => (word.parse::<u64>().map_err(|_| invalid())?, 1),
+    };
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::note::Note;
+    use crate::musical::pitch::Pitch;
+    use crate::tuning::scala::{KeyboardMapping, ScalaError, ScalaScale};
+    use crate::tuning::table::TuningTable;
+
+    const TWELVE_TET: &str = "! 12tet.scl\n!\n12-TET\n 12\n!\n100.0\n200.\n300.0\n400.0\n500.0\n600.0\n700.0\n800.0\n900.0\n1000.0\n1100.0\n2/1\n";
+
+    const JUST_MAJOR: &str = "Just major\n7\n9/8\n5/4\n4/3 fourth\n3/2\n5/3\n15/8\n2\n";
+
+    #[test]
+    fn test_parse() {
+        let scale = ScalaScale::parse(JUST_MAJOR).unwrap();
+        assert_eq!("Just major", scale.description);
+        assert_eq!(7, scale.cents.len());
+        assert!((scale.degree_cents(4) - 701.955).abs() < 0.001);
+        assert!((scale.degree_cents(-3) - -498.045).abs() < 0.001);
+
+        assert_eq!(Err(ScalaError::Missing("pitch")), ScalaScale::parse("Short\n3\n100.0\n"));
+        assert_eq!(Err(ScalaError::Invalid("pitch", "0/4".to_string())), ScalaScale::parse("Zero\n1\n0/4\n"));
+
+        let mapping = KeyboardMapping::parse("! white keys\n7\n0\n127\n60\n69\n432.0\n7\n0\nx\n1\nx\n2\n3\nx\n").unwrap();
+        assert_eq!(vec![Some(0), None, Some(1), None, Some(2), Some(3), None], mapping.map);
+        assert_eq!(Some(15), mapping.degree(76, 7));
+        assert_eq!(None, mapping.degree(61, 7));
+
+        let huge = "100000000\n0\n127\n60\n69\n440.0\n12\n";
+        assert_eq!(Err(ScalaError::Invalid("map size", "100000000".to_string())), KeyboardMapping::parse(huge));
+        assert!(KeyboardMapping::parse(&format!("{}\n0\n127\n60\n69\n440.0\n12\n", usize::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let scale = ScalaScale::parse(TWELVE_TET).unwrap();
+        let table = KeyboardMapping::default().resolve(&scale).unwrap();
+        let equal = TuningTable::equal_temperament();
+        for key in 0..TuningTable::LEN as u8 {
+            assert!((table.voltage(Pitch(key)) - equal.voltage(Pitch(key))).abs() < 1e-5);
+        }
+
+        let scale = ScalaScale::parse(JUST_MAJOR).unwrap();
+        let mapping = KeyboardMapping { reference: 60, frequency: 261.625_565, ..KeyboardMapping::default() };
+        let table = mapping.resolve(&scale).unwrap();
+        assert!((table.voltage(Pitch(60)) - equal.voltage(Pitch(60))).abs() < 1e-5);
+        assert!((table.voltage(Pitch(64)) - (equal.voltage(Pitch(60)) + 701.955 / 1200.0)).abs() < 1e-5);
+        assert!((table.voltage(Pitch(67)) - (equal.voltage(Pitch(72)))).abs() < 1e-5);
+
+        let tuning = mapping.tuning(&scale).unwrap();
+        assert_eq!(7, tuning.steps());
+        assert_eq!(4, tuning.note_steps().step(Note::G));
+        assert!((tuning.voltage(64.0) - table.voltage(Pitch(64))).abs() < 1e-6);
+
+        let unmapped = KeyboardMapping { map: vec![Some(0), None], reference: 61, ..KeyboardMapping::default() };
+        assert_eq!(Err(ScalaError::UnmappedReference), unmapped.resolve(&scale));
+    }
+}
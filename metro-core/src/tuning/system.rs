@@ -0,0 +1,257 @@
+use micromath::F32Ext;
+
+use crate::musical::key::Key;
+use crate::musical::note::Note;
+use crate::musical::pitch::Pitch;
+use crate::tuning::table::TuningTable;
+
+/// How pitches are spaced. Under a tuning a `Pitch` counts steps rather than semitones,
+/// with `Tuning::ROOT` sounding middle C, so `Edo(12)` is the usual MIDI tuning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+// There is no heap to box the table on, and a config only holds the one tuning.
+#[allow(clippy::large_enum_variant)]
+pub enum Tuning {
+    /// The octave divided into the given number of equal steps, e.g. 19, 22 or 31.
+    Edo(u8),
+    Just(JustIntonation),
+    /// A resolved table, e.g. from a Scala file, repeating every given number of steps. Pitches
+    /// play the table's voltages as they are.
+    Table(TuningTable, u8),
+}
+
+impl Tuning {
+    pub const ROOT: Pitch = Pitch(60);
+
+    /// Number of steps in one period.
+    pub fn steps(&self) -> u8 {
+        match self {
+            Tuning::Edo(steps) => (*steps).max(1),
+            Tuning::Just(just) => just.len,
+            Tuning::Table(_, steps) => (*steps).max(1),
+        }
+    }
+
+    /// Cents above the root of `step`, which may lie in any period.
+    pub fn cents(&self, step: i32) -> f32 {
+        match self {
+            Tuning::Edo(_) => step as f32 * 1200.0 / self.steps() as f32,
+            Tuning::Just(just) => just.cents(step),
+            Tuning::Table(table, _) => {
+                let steps = self.steps() as i32;
+                let cents = |step: i32| (table.voltage(Self::ROOT.transpose(step as i16)) - table.voltage(Self::ROOT)) * 1200.0;
+                step.div_euclid(steps) as f32 * cents(steps) + cents(step.rem_euclid(steps))
+            }
+        }
+    }
+
+    /// 1V/oct output voltage of a pitch counted in steps, with the root keeping its 12-TET
+    /// voltage. Fractional pitches, e.g. while sliding, are interpolated between steps.
+    pub fn voltage(&self, pitch: f32) -> f32 {
+        if let Tuning::Table(table, _) = self {
+            return table.voltage_at(pitch);
+        }
+        let step = pitch - Self::ROOT.0 as f32;
+        let low = F32Ext::floor(step);
+        let low_cents = self.cents(low as i32);
+        let cents = low_cents + (self.cents(low as i32 + 1) - low_cents) * (step - low);
+        Self::ROOT.voltage() + cents / 1200.0
+    }
+
+    /// Resolves the tuning into output voltages for every pitch.
+    pub fn table(&self) -> TuningTable {
+        if let Tuning::Table(table, _) = self {
+            return *table;
+        }
+        TuningTable::from_voltages(core::array::from_fn(|i| self.voltage(i as f32)))
+    }
+
+    /// Like `Key::quantize_float`, counting in steps of the tuning.
+    pub fn quantize_float(&self, key: Key, input: f32, base: Pitch, octaves: u8) -> Pitch {
+        key.quantize_float_in(input, base, octaves, self.note_steps())
+    }
+
+    /// Returns the nearest pitch in `key`, which may lie in a neighbouring period. The key's
+    /// notes are read as the steps closest to them, so e.g. C major in 31-EDO comes out as
+    /// the meantone major scale.
+    pub fn quantize(&self, key: Key, input: Pitch) -> Pitch {
+        key.quantize_in(input, self.note_steps())
+    }
+
+    /// The step closest to each note's 12-TET interval above C.
+    pub fn note_steps(&self) -> NoteSteps {
+        NoteSteps {
+            notes: Note::ALL.map(|note| self.nearest_step(note as u8 as f32 * 100.0)),
+            period: self.steps() as i32,
+        }
+    }
+
+    fn nearest_step(&self, cents: f32) -> i32 {
+        let steps = self.steps() as i32;
+        let first = F32Ext::floor(cents / self.cents(steps)) as i32 * steps;
+        (first..=first + steps)
+            .min_by(|&a, &b| {
+                let distance = |step| F32Ext::abs(self.cents(step) - cents);
+                distance(a).partial_cmp(&distance(b)).expect("cents should be finite")
+            })
+            .expect("period should have steps")
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self { Tuning::Edo(12) }
+}
+
+/// Where the twelve notes fall in a tuning, as steps above C, and how many steps the notes
+/// repeat after.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct NoteSteps {
+    notes: [i32; Note::COUNT as usize],
+    period: i32,
+}
+
+impl NoteSteps {
+    /// 12-TET, where every note is a step of its own.
+    pub const EDO_12: NoteSteps = NoteSteps { notes: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11], period: 12 };
+
+    pub fn step(&self, note: Note) -> i32 { self.notes[note as usize] }
+
+    pub fn period(&self) -> i32 { self.period }
+
+    /// Steps in a 12-TET interval of `semitones`, e.g. for chord tones, with each octave
+    /// counting as a period.
+    pub fn interval(&self, semitones: u8) -> i32 {
+        (semitones / Note::COUNT) as i32 * self.period + self.step(Note::from_index(semitones))
+    }
+}
+
+/// Steps given as frequency ratios above the root, e.g. 3/2 for a pure fifth. As in Scala
+/// files, the last ratio is the period the steps repeat at, usually 2/1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JustIntonation {
+    cents: [f32; JustIntonation::CAPACITY],
+    len: u8,
+}
+
+impl JustIntonation {
+    pub const CAPACITY: usize = 32;
+
+    /// Ratios past `CAPACITY` are dropped. A period that doesn't rise above the root is replaced
+    /// by an octave, so no ratios at all gives a single 2/1 step.
+    pub fn new(ratios: &[(u16, u16)]) -> Self {
+        let mut just = JustIntonation { cents: [0.0; Self::CAPACITY], len: 0 };
+        for &(numerator, denominator) in ratios.iter().take(Self::CAPACITY) {
+            just.cents[just.len as usize] = 1200.0 * log2(numerator.max(1) as f32 / denominator.max(1) as f32);
+            just.len += 1;
+        }
+        just.len = just.len.max(1);
+        let period = &mut just.cents[just.len as usize - 1];
+        if *period <= 0.0 {
+            *period = 1200.0;
+        }
+        just
+    }
+
+    /// Cents above the root of each step, ending with the period.
+    pub fn steps(&self) -> &[f32] {
+        &self.cents[..self.len as usize]
+    }
+
+    fn cents(&self, step: i32) -> f32 {
+        let len = self.len as i32;
+        let period = self.cents[len as usize - 1];
+        let offset = match step.rem_euclid(len) {
+            0 => 0.0,
+            i => self.cents[i as usize - 1],
+        };
+        step.div_euclid(len) as f32 * period + offset
+    }
+}
+
+/// `log2` of a positive number, to well within a cent. micromath's version can be a cent
+/// or more out, which is audible on sustained just intervals.
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    // ln(m) = 2 atanh((m - 1) / (m + 1)), where the series converges quickly for m in 1..2
+    let t = (mantissa - 1.0) / (mantissa + 1.0);
+    let t2 = t * t;
+    let atanh = t * (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 * (1.0 / 7.0 + t2 / 9.0))));
+    exponent as f32 + 2.0 * core::f32::consts::LOG2_E * atanh
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::musical::key::Key;
+    use crate::musical::note::Note;
+    use crate::musical::pitch::Pitch;
+    use crate::musical::scale::Scale;
+    use crate::tuning::system::{JustIntonation, NoteSteps, Tuning};
+    use crate::tuning::table::TuningTable;
+
+    #[test]
+    fn test_edo() {
+        let twelve = Tuning::Edo(12);
+        assert_eq!(TuningTable::equal_temperament().voltages().map(|v| (v * 1e4).round()), twelve.table().voltages().map(|v| (v * 1e4).round()));
+        for key in [Key::from(Scale::Major), Key::new(Scale::MinorPentatonic, Note::FSharp)] {
+            for pitch in 0..=Pitch::MAX.0 {
+                assert_eq!(key.quantize(Pitch(pitch)), twelve.quantize(key, Pitch(pitch)));
+            }
+            assert_eq!(key.quantize_float(0.6, Pitch(48), 2), twelve.quantize_float(key, 0.6, Pitch(48), 2));
+        }
+
+        let major = Key::from(Scale::Major);
+        let meantone = Tuning::Edo(31);
+        let degrees = (0..8).map(|i| meantone.quantize_float(major, i as f32 / 13.0, Tuning::ROOT, 2).0 - 60).collect::<Vec<_>>();
+        assert_eq!(vec![0, 5, 10, 13, 18, 23, 28, 31], degrees);
+        assert_eq!(Pitch(65), meantone.quantize(major, Pitch(64)));
+        assert_eq!(Pitch(91), meantone.quantize(major, Pitch(92)));
+        let chromatic = Key::from(Scale::Chromatic);
+        assert!((0..31).all(|step| meantone.quantize(chromatic, Pitch(60 + step)) == Pitch(60 + step)));
+        assert_eq!(Pitch(61), meantone.quantize_float(chromatic, 1.0 / 61.0, Tuning::ROOT, 2));
+        assert_eq!(Pitch(121), meantone.quantize_float(chromatic, 1.0, Tuning::ROOT, 2));
+
+        let nineteen = Tuning::Edo(19);
+        assert!((nineteen.table().voltage(Pitch(79)) - (Tuning::ROOT.voltage() + 1.0)).abs() < 1e-6);
+        assert!((meantone.voltage(91.0) - (Tuning::ROOT.voltage() + 1.0)).abs() < 1e-6);
+        assert!((meantone.voltage(60.5) - (Tuning::ROOT.voltage() + 0.5 / 31.0)).abs() < 1e-6);
+        assert_eq!(Pitch(55), nineteen.quantize(Key::new(Scale::Major, Note::A), Pitch(56)));
+        assert_eq!(NoteSteps::EDO_12, twelve.note_steps());
+        assert_eq!((18, 31, 49), (meantone.note_steps().step(Note::G), meantone.note_steps().period(), meantone.note_steps().interval(19)));
+    }
+
+    #[test]
+    fn test_just_intonation() {
+        let five_limit = JustIntonation::new(&[(16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32), (3, 2), (8, 5), (5, 3), (9, 5), (15, 8), (2, 1)]);
+        assert!((five_limit.steps()[6] - 701.955).abs() < 0.01);
+        assert!((five_limit.steps()[3] - 386.314).abs() < 0.01);
+
+        let just = Tuning::Just(five_limit);
+        let table = just.table();
+        let root = Tuning::ROOT.voltage();
+        assert!((table.voltage(Pitch(67)) - (root + 701.955 / 1200.0)).abs() < 1e-5);
+        assert!((table.voltage(Pitch(52)) - (root - 813.686 / 1200.0)).abs() < 1e-5);
+        assert_eq!(Pitch(62), just.quantize(Key::from(Scale::Major), Pitch(63)));
+
+        assert_eq!([1200.0], JustIntonation::new(&[]).steps());
+        assert_eq!([1200.0], JustIntonation::new(&[(1, 1)]).steps());
+        let flat = Tuning::Just(JustIntonation::new(&[(3, 2), (1, 2)]));
+        assert_eq!(Pitch(62), flat.quantize(Key::from(Scale::Major), Pitch(62)));
+    }
+
+    #[test]
+    fn test_table() {
+        let nineteen = Tuning::Edo(19);
+        let table = Tuning::Table(nineteen.table(), 19);
+        assert_eq!(nineteen.table(), table.table());
+        assert_eq!(nineteen.note_steps(), table.note_steps());
+        assert!((table.cents(-25) - nineteen.cents(-25)).abs() < 0.01);
+        assert!((table.cents(80) - nineteen.cents(80)).abs() < 0.01);
+        assert!((table.voltage(62.5) - nineteen.voltage(62.5)).abs() < 1e-6);
+
+        let mut stretched = TuningTable::equal_temperament();
+        stretched.set_voltage(Pitch(72), Pitch(72).voltage() + 0.01);
+        assert!((Tuning::Table(stretched, 12).voltage(72.0) - (Pitch(72).voltage() + 0.01)).abs() < 1e-6);
+        assert_eq!(1, Tuning::Table(stretched, 0).steps());
+    }
+}
@@ -0,0 +1,48 @@
+use micromath::F32Ext;
+
+use crate::musical::pitch::Pitch;
+
+/// The output voltage of every MIDI note under some tuning. Tunings are resolved into a table
+/// up front, so playing a note is a lookup that needs neither std nor floating point logs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TuningTable {
+    voltages: [f32; TuningTable::LEN],
+}
+
+impl TuningTable {
+    pub const LEN: usize = Pitch::MAX.0 as usize + 1;
+
+    /// 12-TET, matching `Pitch::voltage`.
+    pub fn equal_temperament() -> Self {
+        Self { voltages: core::array::from_fn(|i| Pitch(i as u8).voltage()) }
+    }
+
+    pub fn from_voltages(voltages: [f32; TuningTable::LEN]) -> Self {
+        Self { voltages }
+    }
+
+    pub fn voltages(&self) -> &[f32; TuningTable::LEN] {
+        &self.voltages
+    }
+
+    pub fn set_voltage(&mut self, pitch: Pitch, voltage: f32) {
+        self.voltages[pitch.0.min(Pitch::MAX.0) as usize] = voltage
+    }
+
+    pub fn voltage(&self, pitch: Pitch) -> f32 {
+        self.voltages[pitch.0.min(Pitch::MAX.0) as usize]
+    }
+
+    /// Interpolates between neighbouring notes for fractional pitches, e.g. while sliding.
+    pub fn voltage_at(&self, pitch: f32) -> f32 {
+        let pitch = pitch.clamp(0.0, Pitch::MAX.0 as f32);
+        let low = F32Ext::floor(pitch) as usize;
+        let high = (low + 1).min(Self::LEN - 1);
+        let fract = pitch - low as f32;
+        self.voltages[low] + (self.voltages[high] - self.voltages[low]) * fract
+    }
+}
+
+impl Default for TuningTable {
+    fn default() -> Self { Self::equal_temperament() }
+}
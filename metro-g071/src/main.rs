@@ -30,13 +30,19 @@ use panic_semihosting as _;
 use rt::entry;
 use stm32g0::stm32g071::TIM17;
 
+use metro_core::modulation::lfo::{Lfo, LfoShape};
+use metro_core::modulation::slew::{Slew, SlewLimiter};
 use metro_core::musical::gate::Gate;
+use metro_core::musical::key::Key;
+use metro_core::musical::note::Note;
+use metro_core::musical::pitch::Pitch;
 use metro_core::musical::scale::Scale;
 use metro_core::sequencer::sequencer;
 use metro_core::sequencer::sequencer::GateMode;
 use metro_core::sequencer::stage_mode::StageMode;
 
 const N: usize = 8;
+const OCTAVES: u8 = 3;
 const BPM: u32 = 128;
 const STEP_PM: u32 = BPM * 4;
 const STEP_DUR: u16 = ((60_f32 / STEP_PM as f32) * 1_000_f32) as u16;
@@ -80,13 +86,21 @@ unsafe fn main() -> ! {
 
     // Outputs
     let mut gate = gpiob.pb5.into_push_pull_output();
-    let dac0 = dp.DAC.constrain(gpioa.pa4, &mut rcc);
+    let mut accent = gpiob.pb6.into_push_pull_output();
+    let (dac0, dac1) = dp.DAC.constrain((gpioa.pa4, gpioa.pa5), &mut rcc);
     let mut pitch = dac0.calibrate_buffer(&mut delay).enable();
+    let mut modulation = dac1.calibrate_buffer(&mut delay).enable();
 
-    let mut seq = sequencer::Sequencer::new();
+    let mut seq = sequencer::Sequencer::<N>::new();
     seq.config().set_stage_mode(StageMode::PingPong);
     seq.config().set_gate_time_us((GATE_DUR as u32 * 1000) as u32);
-    let scale = Scale::Chromatic;
+    seq.config().set_pulse_time_us(STEP_DUR as u32 * 1000);
+    let key = Key::new(Scale::Chromatic, Note::C);
+    let mut lfo = Lfo::new(LfoShape::Triangle, N as u8);
+    lfo.set_pulse_time_us(STEP_DUR as u32 * 1000);
+    // Takes the edge off DAC jumps between notes without audible glide
+    let mut pitch_slew = SlewLimiter::new(Slew::Exponential { rise_us: 200, fall_us: 200 }, 0);
+    let mut last_count = 0_u16;
 
     let mut timer = dp.TIM17.timer(&mut rcc);
     timer.start(1000.ms());
@@ -111,7 +125,7 @@ unsafe fn main() -> ! {
         // Configure sequencer
         for s in 0..N {
             let stage = seq.config().stage(s).unwrap();
-            stage.note = scale.quantize_float(pitches[s]);
+            stage.note = key.quantize_float(pitches[s], Pitch::new(Note::C, 0), OCTAVES);
             stage.gate_mode = GateMode::from_float(gate_modes[s]);
             stage.gate_mode = GateMode::Repeat;
             stage.pulse_count = F32Ext::round(pulse_counts[s] * N as f32) as u8;
@@ -121,14 +135,22 @@ unsafe fn main() -> ! {
         seq.config().set_rnd_seed(TIM17::count() as u32); // TODO: Find a better seed
 
         //Trigger Step
-        if TIM17::count() >= STEP_DUR {
+        let next_pulse = (seq.next_pulse_us() / 1000) as u16;
+        if TIM17::count() >= next_pulse {
             seq.step();
-            TIM17::sub(STEP_DUR);
+            lfo.step();
+            TIM17::sub(next_pulse);
+            last_count = last_count.saturating_sub(next_pulse);
         }
 
         //Get state of sequencer
         let state = seq.state(TIM17::count() as u32 * 1000_u32); // TODO: Refactor to ms
-        pitch.set_value((state.note.voltage() * (4_095_f32 / 3.3)) as u16);
+        let count = TIM17::count();
+        let elapsed_us = count.saturating_sub(last_count) as u32 * 1000;
+        last_count = count;
+        let pitch_code = (state.voltage * (4_095_f32 / 3.3)) as i32;
+        pitch.set_value(pitch_slew.update(pitch_code, elapsed_us) as u16);
+        modulation.set_value((lfo.value(TIM17::count() as u32 * 1000_u32) * 4_095_f32) as u16);
         mux_out.set_channel(state.pos.stage);
         match state.gate {
             Gate::Open => {
@@ -140,6 +162,10 @@ unsafe fn main() -> ! {
                 gate_led.set_low().unwrap();
             }
         }
+        match state.accent {
+            Gate::Open => accent.set_high().unwrap(),
+            Gate::Closed => accent.set_low().unwrap(),
+        }
     }
 }
 